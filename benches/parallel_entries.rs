@@ -0,0 +1,94 @@
+//! Compares extracting a large zip archive with one thread against several.
+//!
+//! Builds a synthetic archive of many deflated entries in a temporary directory, then times the
+//! binary extracting all of them with increasing `--jobs`. Run with `cargo bench`.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+const ENTRIES: usize = 1000;
+const ENTRY_SIZE: usize = 256 * 1024;
+const RUNS: usize = 3;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let archive = dir.path().join("large.zip");
+    write_archive(&archive)?;
+    println!(
+        "{} entries of {} KiB, {} MiB compressed",
+        ENTRIES,
+        ENTRY_SIZE / 1024,
+        archive.metadata()?.len() / (1024 * 1024)
+    );
+
+    let cores = thread::available_parallelism().map_or(1, |cores| cores.get());
+    let mut jobs = vec![1, 2, 4, 8, cores];
+    jobs.sort_unstable();
+    jobs.dedup();
+    println!("{} CPU cores", cores);
+
+    let mut baseline = None;
+    for n in jobs {
+        let best = (0..RUNS)
+            .map(|run| extract(&archive, &dir.path().join(format!("out-{}-{}", n, run)), n))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .min()
+            .unwrap_or_default();
+        let baseline = *baseline.get_or_insert(best);
+        println!(
+            "--jobs {:>2}: {:>8.3} s  ({:.2}x)",
+            n,
+            best.as_secs_f64(),
+            baseline.as_secs_f64() / best.as_secs_f64()
+        );
+    }
+    Ok(())
+}
+
+/// Writes an archive of `ENTRIES` files of pseudo-random text, which compresses about 2:1 so
+/// that inflating it takes real work.
+fn write_archive(path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut zip = ZipWriter::new(BufWriter::new(File::create(path)?));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut data = vec![0; ENTRY_SIZE];
+    for index in 0..ENTRIES {
+        for byte in &mut data {
+            // xorshift64
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            *byte = b'a' + (state % 16) as u8;
+        }
+        zip.start_file(format!("data/{:05}.dat", index), options)?;
+        zip.write_all(&data)?;
+    }
+    zip.finish()?.flush()?;
+    Ok(())
+}
+
+/// Extracts every entry of `archive` into `output` with `--jobs jobs`, returning the time taken.
+fn extract(archive: &Path, output: &Path, jobs: usize) -> Result<Duration, Box<dyn Error>> {
+    let start = Instant::now();
+    let status = Command::new(env!("CARGO_BIN_EXE_extract_filetype_from_zip"))
+        .arg("--input")
+        .arg(archive)
+        .args(["--extension", "dat", "--jobs", &jobs.to_string(), "--output"])
+        .arg(output)
+        .stdout(Stdio::null())
+        .status()?;
+    let elapsed = start.elapsed();
+    if !status.success() {
+        return Err(format!("extraction with --jobs {} failed: {}", jobs, status).into());
+    }
+    Ok(elapsed)
+}
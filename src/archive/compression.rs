@@ -0,0 +1,116 @@
+use std::io::{self, Read};
+
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use xz2::read::XzDecoder;
+
+use super::has_suffix;
+use crate::sniff::{self, FileKind};
+
+/// A single-stream compression format, wrapping either a tar archive or a single file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+}
+
+impl Compression {
+    /// Returns the compression format for a kind detected by `sniff::detect`, if it is one.
+    pub fn from_kind(kind: FileKind) -> Option<Self> {
+        match kind {
+            sniff::GZIP => Some(Compression::Gzip),
+            sniff::BZIP2 => Some(Compression::Bzip2),
+            sniff::XZ => Some(Compression::Xz),
+            sniff::ZSTD => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Returns the compression format implied by a file name suffix such as ".gz".
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Compression::Gzip, Compression::Bzip2, Compression::Xz, Compression::Zstd]
+            .into_iter()
+            .find(|compression| compression.suffixes().iter().any(|suffix| has_suffix(name, suffix)))
+    }
+
+    /// File name suffixes used for files compressed with this format.
+    pub fn suffixes(self) -> &'static [&'static str] {
+        match self {
+            Compression::Gzip => &[".gz", ".gzip"],
+            Compression::Bzip2 => &[".bz2"],
+            Compression::Xz => &[".xz"],
+            Compression::Zstd => &[".zst", ".zstd"],
+        }
+    }
+
+    /// Works out the name of the file inside a compressed file called `outer_name`.
+    /// Gzip can record the original file name in its header, which is preferred; otherwise the
+    /// compression suffix is removed ("logs/report.csv.gz" becomes "logs/report.csv").
+    /// Any directory in `outer_name` is kept either way.
+    pub fn inner_name(self, outer_name: &str, head: &[u8]) -> String {
+        if self == Compression::Gzip {
+            if let Some(file_name) = GzipHeader::parse(head).and_then(|header| header.file_name) {
+                return match outer_name.rfind(['/', '\\']) {
+                    Some(pos) => format!("{}{}", &outer_name[..=pos], file_name),
+                    None => file_name,
+                };
+            }
+        }
+        self.suffixes()
+            .iter()
+            .find(|suffix| has_suffix(outer_name, suffix))
+            .map(|suffix| outer_name[..outer_name.len() - suffix.len()].to_string())
+            .unwrap_or_else(|| outer_name.to_string())
+    }
+
+    /// Wraps `reader` so that reading from it yields the decompressed data.
+    /// Concatenated streams (e.g. from `cat a.gz b.gz`) are decoded as one.
+    pub fn decoder<'a>(self, reader: impl Read + 'a) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Compression::Gzip => Box::new(MultiGzDecoder::new(reader)),
+            Compression::Bzip2 => Box::new(MultiBzDecoder::new(reader)),
+            Compression::Xz => Box::new(XzDecoder::new_multi_decoder(reader)),
+            Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(reader)?),
+        })
+    }
+}
+
+/// The optional fields of a gzip member header (RFC 1952) that describe the original file.
+#[derive(Debug, Default)]
+pub struct GzipHeader {
+    /// Original file name (FNAME), without any directory.
+    pub file_name: Option<String>,
+    /// Original modification time (MTIME) as a Unix timestamp; 0 means not recorded.
+    pub mtime: u32,
+}
+
+impl GzipHeader {
+    const FEXTRA: u8 = 0x04;
+    const FNAME: u8 = 0x08;
+
+    /// Parses the header at the start of `head`, or returns `None` if it is not gzip
+    /// or is truncated before the fields of interest.
+    pub fn parse(head: &[u8]) -> Option<Self> {
+        if head.len() < 10 || head[0..2] != [0x1f, 0x8b] {
+            return None;
+        }
+        let flags = head[3];
+        let mtime = u32::from_le_bytes([head[4], head[5], head[6], head[7]]);
+        let mut pos = 10;
+        if flags & Self::FEXTRA != 0 {
+            let extra_len = u16::from_le_bytes([*head.get(pos)?, *head.get(pos + 1)?]) as usize;
+            pos += 2 + extra_len;
+        }
+        let mut file_name = None;
+        if flags & Self::FNAME != 0 {
+            let rest = head.get(pos..)?;
+            let end = rest.iter().position(|&b| b == 0)?;
+            // FNAME is ISO 8859-1, in which every byte is the code point of the same value.
+            let name: String = rest[..end].iter().map(|&b| b as char).collect();
+            file_name = name.rsplit(['/', '\\']).next().filter(|name| !name.is_empty()).map(str::to_string);
+        }
+        Some(GzipHeader { file_name, mtime })
+    }
+}
@@ -0,0 +1,281 @@
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fmt;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use time::{Date, OffsetDateTime, PrimitiveDateTime, UtcOffset};
+
+use crate::error::ErrorKind;
+use crate::limits::{CountingReader, ReadCount};
+use crate::password::Passwords;
+use crate::sniff;
+
+mod compression;
+mod sevenz_reader;
+mod single_reader;
+mod tar_reader;
+mod zip_reader;
+
+pub use compression::Compression;
+
+use sevenz_reader::SevenZipReader;
+use single_reader::SingleFileReader;
+use tar_reader::TarReader;
+use zip_reader::ZipReader;
+
+/// File name suffixes of the archives picked up when scanning a directory.
+/// A single input file is detected by its content instead, whatever its name.
+const ARCHIVE_SUFFIXES: &[&str] = &[
+    ".zip", ".7z", ".tar", ".tar.gz", ".tgz", ".tar.bz2", ".tbz", ".tbz2", ".tar.xz", ".txz", ".tar.zst", ".tzst",
+];
+
+/// Anything an archive can be read from.
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// When an entry was last modified, as recorded in the archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryTime {
+    /// An MS-DOS date and time, which has no time zone and is usually the creator's local time.
+    Dos(PrimitiveDateTime),
+    /// An exact point in time, e.g. from a tar header.
+    Utc(OffsetDateTime),
+}
+
+impl EntryTime {
+    /// The calendar date, in the archive's own notion of time.
+    pub fn date(&self) -> Date {
+        match self {
+            EntryTime::Dos(time) => time.date(),
+            EntryTime::Utc(time) => time.date(),
+        }
+    }
+
+    /// The point in time, taking DOS times to be at `dos_offset` from UTC, or if that is `None`,
+    /// in the local time zone with the offset in effect at that time, so that times on either
+    /// side of a daylight saving time change are both right.
+    pub fn to_offset_date_time(self, dos_offset: Option<UtcOffset>) -> OffsetDateTime {
+        match self {
+            EntryTime::Dos(time) => time.assume_offset(dos_offset.unwrap_or_else(|| local_offset_at(time))),
+            EntryTime::Utc(time) => time,
+        }
+    }
+}
+
+/// The local time zone's offset from UTC at the local date and time `time`, or UTC if it cannot
+/// be determined. Which offset applies depends on the moment, which depends on the offset, so
+/// the offset at `time` taken as UTC, which is at most a day out, is used to find the moment.
+fn local_offset_at(time: PrimitiveDateTime) -> UtcOffset {
+    let offset_at = |offset| UtcOffset::local_offset_at(time.assume_offset(offset)).unwrap_or(UtcOffset::UTC);
+    offset_at(offset_at(UtcOffset::UTC))
+}
+
+impl fmt::Display for EntryTime {
+    /// Formats as "YYYY-MM-DD HH:MM:SS", with " UTC" appended when the time zone is known.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (time, zone) = match self {
+            EntryTime::Dos(time) => (*time, ""),
+            EntryTime::Utc(time) => (PrimitiveDateTime::new(time.date(), time.time()), " UTC"),
+        };
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}{}",
+            time.year(),
+            u8::from(time.month()),
+            time.day(),
+            time.hour(),
+            time.minute(),
+            time.second(),
+            zone
+        )
+    }
+}
+
+/// Metadata of one archive entry, available before its data is read.
+#[derive(Debug, Clone)]
+pub struct EntryMeta {
+    /// The entry name exactly as stored in the archive. Not safe to use as a path.
+    pub name: String,
+    /// Whether the entry is a regular file (as opposed to a directory, link, etc.).
+    pub is_file: bool,
+    /// Whether the entry's data is encrypted.
+    pub encrypted: bool,
+    /// Uncompressed size declared by the archive, or `None` for formats that do not record it.
+    pub size: Option<u64>,
+    /// Compressed size declared by the archive; equal to `size` for uncompressed entries.
+    pub compressed_size: u64,
+    /// CRC-32 of the uncompressed data, for formats that record one.
+    pub crc32: Option<u32>,
+    /// Last modification time, if recorded.
+    pub modified: Option<EntryTime>,
+    /// Unix file mode, including permission bits, if recorded.
+    pub unix_mode: Option<u32>,
+}
+
+/// The result of opening an entry's data.
+pub enum Opened<'a> {
+    /// The decompressed (and decrypted) data.
+    Data(Box<dyn Read + 'a>),
+    /// The entry is encrypted and none of the passwords worked; the reason says why.
+    Undecryptable(&'static str),
+}
+
+/// One entry of an archive, passed to the callback of `ArchiveReader::for_each_entry`.
+pub trait Entry {
+    fn meta(&self) -> &EntryMeta;
+
+    /// Opens the entry's data. Entries that are never opened are skipped cheaply.
+    fn open(&mut self) -> Result<Opened<'_>, Box<dyn Error>>;
+
+    /// For an entry of an archive compressed as a whole, such as a compressed tarball, whose
+    /// own compressed size is unknown, counts the compressed bytes read from the archive.
+    fn compressed_count(&self) -> Option<ReadCount> {
+        None
+    }
+}
+
+/// Callback that `ArchiveReader::for_each_entry` calls for each entry.
+pub type EntryVisitor<'v> = dyn FnMut(&mut dyn Entry) -> Result<(), Box<dyn Error>> + 'v;
+
+/// A format-independent view of an archive as a sequence of entries.
+pub trait ArchiveReader {
+    /// Number of entries, if it is known before reading them.
+    fn entry_count(&self) -> Option<usize>;
+
+    /// Calls `visit` for each entry in archive order, stopping at the first error.
+    fn for_each_entry(&mut self, visit: &mut EntryVisitor) -> Result<(), Box<dyn Error>>;
+
+    /// The names of all entries that are regular files, for formats that list their entries
+    /// before their data, or `None` for formats read as a stream.
+    fn file_names(&mut self) -> Result<Option<Vec<String>>, Box<dyn Error>> {
+        Ok(None)
+    }
+
+    /// Whether entries can be read one at a time, in any order, with `visit_entry`.
+    fn random_access(&self) -> bool {
+        false
+    }
+
+    /// Calls `visit` for the entry at `index` in archive order. Only supported by readers for
+    /// which `random_access` returns true.
+    fn visit_entry(&mut self, index: usize, visit: &mut EntryVisitor) -> Result<(), Box<dyn Error>> {
+        let _ = (index, visit);
+        Err("entries of this archive can only be read in order".into())
+    }
+}
+
+/// Opens an archive, detecting its format from its leading bytes. `name` is only used as a
+/// fallback for formats without a reliable signature, and to name the contents of a single
+/// compressed file, which is opened as an archive with one entry. Encrypted zip entries and
+/// encrypted 7z archives are decrypted with `passwords`.
+pub fn open<'a>(
+    mut input: Box<dyn ReadSeek + 'a>,
+    name: &str,
+    passwords: &'a Passwords,
+) -> Result<Box<dyn ArchiveReader + 'a>, Box<dyn Error>> {
+    let head = read_head(&mut input)?;
+    input.seek(SeekFrom::Start(0))?;
+
+    match sniff::detect(&head) {
+        Some(sniff::ZIP) => return Ok(Box::new(ZipReader::new(input, passwords)?)),
+        Some(sniff::SEVEN_ZIP) => return Ok(Box::new(SevenZipReader::new(input, passwords)?)),
+        Some(sniff::TAR) => return Ok(Box::new(TarReader::new(input))),
+        Some(kind) => {
+            if let Some(compression) = Compression::from_kind(kind) {
+                // Look inside the compressed stream to tell a tarball from any other compressed file.
+                let inner_head = read_head(&mut compression.decoder(&mut input)?)?;
+                input.seek(SeekFrom::Start(0))?;
+                if sniff::detect(&inner_head) == Some(sniff::TAR) || has_suffix(name, ".tar") {
+                    let (input, compressed_count) = CountingReader::new(input);
+                    return Ok(Box::new(TarReader::compressed(compression.decoder(input)?, compressed_count)));
+                }
+                let compressed_size = input.seek(SeekFrom::End(0))?;
+                input.seek(SeekFrom::Start(0))?;
+                return Ok(Box::new(SingleFileReader::new(input, compression, name, &head, compressed_size)?));
+            }
+        }
+        None => {}
+    }
+
+    // Fall back to the name for formats whose signature may be missing,
+    // such as pre-POSIX tar files or self-extracting zips.
+    if has_suffix(name, ".tar") {
+        Ok(Box::new(TarReader::new(input)))
+    } else if has_suffix(name, ".zip") {
+        match ZipReader::new(input, passwords) {
+            Ok(reader) => Ok(Box::new(reader)),
+            Err(e) => Err(crate::Error::new(ErrorKind::NotAnArchive, format!("not a zip archive: {}", e)).into()),
+        }
+    } else {
+        Err(crate::Error::new(ErrorKind::NotAnArchive, "not a supported archive").into())
+    }
+}
+
+/// Opens a single compressed file as an archive with one entry, without looking inside it for
+/// a tar archive. `head` holds the first bytes of `input`, which `input` must still start with.
+pub fn open_compressed<'a>(
+    input: Box<dyn Read + 'a>,
+    compression: Compression,
+    name: &str,
+    head: &[u8],
+    compressed_size: u64,
+) -> Result<Box<dyn ArchiveReader + 'a>, Box<dyn Error>> {
+    Ok(Box::new(SingleFileReader::new(input, compression, name, head, compressed_size)?))
+}
+
+/// Returns true if the path's name ends with one of the known archive suffixes (case-insensitive).
+pub fn is_archive_path(path: &Path) -> bool {
+    path.file_name()
+        .and_then(OsStr::to_str)
+        .is_some_and(|name| ARCHIVE_SUFFIXES.iter().any(|suffix| has_suffix(name, suffix)))
+}
+
+/// Returns true if the path looks like something that can be given as input: an archive, or a
+/// single compressed file such as "report.csv.gz".
+pub fn is_input_path(path: &Path) -> bool {
+    is_archive_path(path) || path.file_name().and_then(OsStr::to_str).and_then(Compression::from_name).is_some()
+}
+
+fn has_suffix(name: &str, suffix: &str) -> bool {
+    name.len() > suffix.len()
+        && name.is_char_boundary(name.len() - suffix.len())
+        && name[name.len() - suffix.len()..].eq_ignore_ascii_case(suffix)
+}
+
+/// Reads up to `sniff::SNIFF_LEN` bytes from the start of `reader`.
+fn read_head(reader: &mut impl Read) -> std::io::Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(sniff::SNIFF_LEN);
+    reader.take(sniff::SNIFF_LEN as u64).read_to_end(&mut head)?;
+    Ok(head)
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{Month, Time};
+
+    use super::*;
+
+    fn dos_time(month: Month, hour: u8) -> PrimitiveDateTime {
+        let date = Date::from_calendar_date(2024, month, 15).unwrap();
+        PrimitiveDateTime::new(date, Time::from_hms(hour, 0, 0).unwrap())
+    }
+
+    #[test]
+    fn dos_times_get_the_local_offset_in_effect_at_them() {
+        for time in [dos_time(Month::January, 12), dos_time(Month::July, 12), dos_time(Month::March, 2)] {
+            let moment = EntryTime::Dos(time).to_offset_date_time(None);
+            assert_eq!(PrimitiveDateTime::new(moment.date(), moment.time()), time);
+            assert_eq!(moment.offset(), UtcOffset::local_offset_at(moment).unwrap_or(UtcOffset::UTC));
+        }
+    }
+
+    #[test]
+    fn dos_times_can_be_given_a_fixed_offset() {
+        let offset = UtcOffset::from_hms(-5, 0, 0).unwrap();
+        let moment = EntryTime::Dos(dos_time(Month::July, 12)).to_offset_date_time(Some(offset));
+        assert_eq!(moment.offset(), offset);
+        assert_eq!(moment.hour(), 12);
+    }
+}
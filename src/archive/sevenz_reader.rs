@@ -0,0 +1,220 @@
+use std::error::Error;
+use std::io::{self, Read, Seek};
+use std::thread;
+
+use sevenz_rust2::{Archive, ArchiveEntry, Block, BlockDecoder, EncoderMethod, Password};
+use time::OffsetDateTime;
+
+use super::{ArchiveReader, Entry, EntryMeta, EntryTime, EntryVisitor, Opened};
+use crate::error::ErrorKind;
+use crate::password::Passwords;
+
+/// How much of a block's first entry is decoded to check a password. Smaller entries are
+/// decoded in full and checked against their CRC-32.
+const PASSWORD_CHECK_LEN: u64 = 64 * 1024;
+
+/// Reads 7z archives, including solid ones where many entries share one LZMA/LZMA2 stream.
+/// Entries are decoded in archive order, so in a solid block every entry before a match is
+/// decompressed too, even if it is skipped.
+pub struct SevenZipReader<'a, R: Read + Seek> {
+    archive: Archive,
+    source: R,
+    passwords: &'a Passwords,
+    /// Index of the password that last worked, which is tried first for the next block.
+    preferred_password: Option<usize>,
+}
+
+impl<'a, R: Read + Seek> SevenZipReader<'a, R> {
+    /// Opens the archive with the first candidate password that can read its (possibly
+    /// encrypted) header, or without a password. The password for the contents is only chosen
+    /// once they are read, as the header need not be encrypted along with them.
+    pub fn new(mut reader: R, passwords: &'a Passwords) -> Result<Self, Box<dyn Error>> {
+        let mut last_error = None;
+        let candidates = passwords.iter().map(password_from_bytes).map(Some);
+        for (index, password) in candidates.chain([None]).enumerate() {
+            reader.rewind()?;
+            match Archive::read(&mut reader, password.as_ref().unwrap_or(&Password::empty())) {
+                Ok(archive) => {
+                    return Ok(SevenZipReader {
+                        archive,
+                        source: reader,
+                        passwords,
+                        // Any password that decrypted the header is most likely the one for the rest.
+                        preferred_password: password.is_some().then_some(index),
+                    });
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.map(classify).unwrap_or_else(|| "cannot open 7z archive".into()))
+    }
+
+    /// Returns the password for block `block_index`: none if it is not encrypted, and otherwise
+    /// the first candidate, starting with the one that worked last, that really decodes it.
+    /// Opening an encrypted block never fails by itself, whatever the password.
+    fn block_password(&mut self, block_index: usize) -> Result<Password, Box<dyn Error>> {
+        if !is_encrypted(&self.archive.blocks[block_index]) {
+            return Ok(Password::empty());
+        }
+        let (archive, source) = (&self.archive, &mut self.source);
+        let found = self.passwords.find(self.preferred_password, |password| {
+            decodes(archive, block_index, &password_from_bytes(password), source)
+        });
+        match found {
+            Some(found) => {
+                self.preferred_password = Some(found);
+                Ok(password_from_bytes(self.passwords.get(found)))
+            }
+            None if self.passwords.is_empty() => {
+                Err(crate::Error::new(ErrorKind::Encrypted, "encrypted 7z archive: no password was given").into())
+            }
+            None => Err(crate::Error::new(ErrorKind::Encrypted, "encrypted 7z archive: none of the passwords matched").into()),
+        }
+    }
+}
+
+impl<R: Read + Seek> ArchiveReader for SevenZipReader<'_, R> {
+    fn entry_count(&self) -> Option<usize> {
+        Some(self.archive.files.len())
+    }
+
+    fn file_names(&mut self) -> Result<Option<Vec<String>>, Box<dyn Error>> {
+        let files = self.archive.files.iter().filter(|entry| !entry.is_directory && !entry.is_anti_item);
+        Ok(Some(files.map(|entry| entry.name.clone()).collect()))
+    }
+
+    fn for_each_entry(&mut self, visit: &mut EntryVisitor) -> Result<(), Box<dyn Error>> {
+        // The decoder's callback can only fail with its own error type, so our errors are
+        // kept aside and decoding is stopped early instead.
+        let mut failure = None;
+        let mut each = |entry: &ArchiveEntry, data: &mut dyn Read, compressed_size: u64| {
+            let mut sevenz_entry = SevenZipEntry {
+                data,
+                meta: meta_from(entry, compressed_size),
+            };
+            if let Err(e) = visit(&mut sevenz_entry) {
+                failure = Some(e);
+                return Ok(false);
+            }
+            // Entries in a solid block share one stream, so skip over whatever was not read.
+            io::copy(sevenz_entry.data, &mut io::sink())?;
+            Ok(true)
+        };
+
+        let threads = thread::available_parallelism().map_or(1, |threads| threads.get() as u32);
+        let mut stopped = false;
+        for block_index in 0..self.archive.blocks.len() {
+            let password = self.block_password(block_index)?;
+            let archive = &self.archive;
+            let decoder = BlockDecoder::new(threads, block_index, archive, &password, &mut self.source);
+            let mut each_in_block = |entry: &ArchiveEntry, data: &mut dyn Read| {
+                each(entry, data, compressed_share(archive, block_index, entry.size))
+            };
+            if !decoder.for_each_entries(&mut each_in_block).map_err(classify)? {
+                stopped = true;
+                break;
+            }
+        }
+        // Empty files belong to no block.
+        if !stopped {
+            for (file_index, entry) in self.archive.files.iter().enumerate() {
+                let in_block = self.archive.stream_map.file_block_index[file_index].is_some();
+                if !in_block && !each(entry, &mut io::empty(), 0).map_err(classify)? {
+                    break;
+                }
+            }
+        }
+        match failure {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Whether a block is encrypted, and so needs a password to decode.
+fn is_encrypted(block: &Block) -> bool {
+    block.coders.iter().any(|coder| coder.encoder_method_id() == EncoderMethod::ID_AES256_SHA256)
+}
+
+/// Whether `password` decodes block `block_index`. The decoder cannot tell a wrong password by
+/// itself, so the start of the block's first entry is decoded; decompressing data decrypted
+/// with the wrong key fails, as does the CRC-32 check of an entry decoded in full.
+fn decodes<R: Read + Seek>(archive: &Archive, block_index: usize, password: &Password, source: &mut R) -> bool {
+    let decoder = BlockDecoder::new(1, block_index, archive, password, source);
+    decoder
+        .for_each_entries(&mut |_, data| {
+            io::copy(&mut data.take(PASSWORD_CHECK_LEN), &mut io::sink())?;
+            Ok(false)
+        })
+        .is_ok()
+}
+
+/// The compressed size of an entry of `size` bytes in block `block_index`: its share of the
+/// block's packed size in proportion to its size. The entries of a solid block share one
+/// compressed stream, so each of them gets the compression ratio of the block as a whole.
+fn compressed_share(archive: &Archive, block_index: usize, size: u64) -> u64 {
+    let first_pack_stream = archive.stream_map.block_first_pack_stream_index()[block_index];
+    let end_pack_stream = archive
+        .stream_map
+        .block_first_pack_stream_index()
+        .get(block_index + 1)
+        .copied()
+        .unwrap_or(archive.pack_sizes().len());
+    let packed: u64 = archive.pack_sizes().get(first_pack_stream..end_pack_stream).unwrap_or_default().iter().sum();
+    let unpacked = archive.blocks[block_index].get_unpack_size().max(1);
+    (size as u128 * packed as u128).div_ceil(unpacked as u128) as u64
+}
+
+/// Tells errors caused by a missing or wrong password apart from other problems with the archive.
+fn classify(error: sevenz_rust2::Error) -> Box<dyn Error> {
+    match error {
+        sevenz_rust2::Error::PasswordRequired | sevenz_rust2::Error::MaybeBadPassword(_) => {
+            crate::Error::new(ErrorKind::Encrypted, error).into()
+        }
+        error => error.into(),
+    }
+}
+
+/// Windows attribute flag set by Unix archivers (p7zip) when the high 16 bits hold a Unix mode.
+const UNIX_EXTENSION: u32 = 0x8000;
+
+/// Describes an entry, whose `compressed_size` is worked out by `compressed_share`.
+fn meta_from(entry: &ArchiveEntry, compressed_size: u64) -> EntryMeta {
+    EntryMeta {
+        name: entry.name.clone(),
+        is_file: !entry.is_directory && !entry.is_anti_item,
+        encrypted: false,
+        size: Some(entry.size),
+        compressed_size,
+        crc32: entry.has_crc.then_some(entry.crc as u32),
+        modified: entry
+            .has_last_modified_date
+            .then(|| EntryTime::Utc(OffsetDateTime::from(std::time::SystemTime::from(entry.last_modified_date)))),
+        // Unix archivers store the mode in the high 16 bits of the Windows attributes and flag it.
+        unix_mode: (entry.has_windows_attributes && entry.windows_attributes & UNIX_EXTENSION != 0)
+            .then_some(entry.windows_attributes >> 16),
+    }
+}
+
+/// 7z passwords are text; candidates that are not valid UTF-8 are used as raw UTF-16 bytes.
+fn password_from_bytes(bytes: &[u8]) -> Password {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Password::from(text),
+        Err(_) => Password::from_raw(bytes),
+    }
+}
+
+struct SevenZipEntry<'a> {
+    data: &'a mut dyn Read,
+    meta: EntryMeta,
+}
+
+impl Entry for SevenZipEntry<'_> {
+    fn meta(&self) -> &EntryMeta {
+        &self.meta
+    }
+
+    fn open(&mut self) -> Result<Opened<'_>, Box<dyn Error>> {
+        Ok(Opened::Data(Box::new(&mut *self.data)))
+    }
+}
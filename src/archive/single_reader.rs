@@ -0,0 +1,73 @@
+use std::error::Error;
+use std::io::{self, Read};
+
+use time::OffsetDateTime;
+
+use super::compression::GzipHeader;
+use super::{ArchiveReader, Compression, Entry, EntryMeta, EntryTime, EntryVisitor, Opened};
+
+/// Presents a single compressed file, such as "report.csv.gz", as an archive with one entry.
+/// The decompressed size is not recorded by these formats, so it is only known once read.
+pub struct SingleFileReader<'a> {
+    data: Option<Box<dyn Read + 'a>>,
+    meta: EntryMeta,
+}
+
+impl<'a> SingleFileReader<'a> {
+    /// `input` is the compressed stream, starting with `head`; `name` is the compressed file's
+    /// name, from which the entry name is derived.
+    pub fn new(
+        input: impl Read + 'a,
+        compression: Compression,
+        name: &str,
+        head: &[u8],
+        compressed_size: u64,
+    ) -> io::Result<Self> {
+        let modified = match compression {
+            Compression::Gzip => GzipHeader::parse(head)
+                .filter(|header| header.mtime != 0)
+                .and_then(|header| OffsetDateTime::from_unix_timestamp(header.mtime as i64).ok())
+                .map(EntryTime::Utc),
+            _ => None,
+        };
+        Ok(SingleFileReader {
+            data: Some(compression.decoder(input)?),
+            meta: EntryMeta {
+                name: compression.inner_name(name, head),
+                is_file: true,
+                encrypted: false,
+                size: None,
+                compressed_size,
+                crc32: None,
+                modified,
+                unix_mode: None,
+            },
+        })
+    }
+}
+
+impl ArchiveReader for SingleFileReader<'_> {
+    fn entry_count(&self) -> Option<usize> {
+        Some(1)
+    }
+
+    fn for_each_entry(&mut self, visit: &mut EntryVisitor) -> Result<(), Box<dyn Error>> {
+        let data = self.data.take().ok_or("compressed file has already been read")?;
+        visit(&mut SingleFileEntry { data, meta: &self.meta })
+    }
+}
+
+struct SingleFileEntry<'a, 'm> {
+    data: Box<dyn Read + 'a>,
+    meta: &'m EntryMeta,
+}
+
+impl Entry for SingleFileEntry<'_, '_> {
+    fn meta(&self) -> &EntryMeta {
+        self.meta
+    }
+
+    fn open(&mut self) -> Result<Opened<'_>, Box<dyn Error>> {
+        Ok(Opened::Data(Box::new(&mut self.data)))
+    }
+}
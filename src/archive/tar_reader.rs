@@ -0,0 +1,83 @@
+use std::error::Error;
+use std::io::Read;
+
+use tar::Archive;
+use time::OffsetDateTime;
+
+use super::{ArchiveReader, Entry, EntryMeta, EntryTime, EntryVisitor, Opened};
+use crate::limits::ReadCount;
+
+/// Reads tar archives, optionally through a decompressor. Tar is read as a stream, so
+/// entries can only be visited once and in order.
+pub struct TarReader<R: Read> {
+    archive: Archive<R>,
+    /// For a compressed tarball, the compressed bytes read so far.
+    compressed_count: Option<ReadCount>,
+}
+
+impl<R: Read> TarReader<R> {
+    pub fn new(reader: R) -> Self {
+        TarReader {
+            archive: Archive::new(reader),
+            compressed_count: None,
+        }
+    }
+
+    /// Reads a tarball through a decompressor, whose input is counted by `compressed_count`.
+    pub fn compressed(reader: R, compressed_count: ReadCount) -> Self {
+        TarReader {
+            archive: Archive::new(reader),
+            compressed_count: Some(compressed_count),
+        }
+    }
+}
+
+impl<R: Read> ArchiveReader for TarReader<R> {
+    fn entry_count(&self) -> Option<usize> {
+        None
+    }
+
+    fn for_each_entry(&mut self, visit: &mut EntryVisitor) -> Result<(), Box<dyn Error>> {
+        for entry in self.archive.entries()? {
+            let entry = entry?;
+            let header = entry.header();
+            let meta = EntryMeta {
+                name: String::from_utf8_lossy(&entry.path_bytes()).into_owned(),
+                is_file: header.entry_type().is_file(),
+                encrypted: false,
+                size: Some(entry.size()),
+                compressed_size: entry.size(),
+                crc32: None,
+                modified: header
+                    .mtime()
+                    .ok()
+                    .and_then(|mtime| OffsetDateTime::from_unix_timestamp(mtime as i64).ok())
+                    .map(EntryTime::Utc),
+                unix_mode: header.mode().ok(),
+            };
+            let compressed_count = self.compressed_count.clone();
+            visit(&mut TarEntry { entry, meta, compressed_count })?;
+        }
+        Ok(())
+    }
+}
+
+struct TarEntry<'a, R: Read> {
+    entry: tar::Entry<'a, R>,
+    meta: EntryMeta,
+    compressed_count: Option<ReadCount>,
+}
+
+impl<R: Read> Entry for TarEntry<'_, R> {
+    fn meta(&self) -> &EntryMeta {
+        &self.meta
+    }
+
+    fn open(&mut self) -> Result<Opened<'_>, Box<dyn Error>> {
+        Ok(Opened::Data(Box::new(&mut self.entry)))
+    }
+
+    fn compressed_count(&self) -> Option<ReadCount> {
+        self.compressed_count.clone()
+    }
+}
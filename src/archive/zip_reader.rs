@@ -0,0 +1,194 @@
+use std::error::Error;
+use std::io::{self, Read, Seek};
+
+use time::{OffsetDateTime, PrimitiveDateTime};
+use zip::read::ZipArchive;
+use zip::ExtraField;
+
+use super::{ArchiveReader, Entry, EntryMeta, EntryTime, EntryVisitor, Opened};
+use crate::password::Passwords;
+
+/// Reads zip archives, decrypting entries with the first candidate password that works.
+pub struct ZipReader<'a, R> {
+    archive: ZipArchive<R>,
+    passwords: &'a Passwords,
+    /// Index of the password that last worked, which is tried first for the next entry.
+    preferred_password: Option<usize>,
+}
+
+impl<'a, R: Read + Seek> ZipReader<'a, R> {
+    pub fn new(reader: R, passwords: &'a Passwords) -> Result<Self, Box<dyn Error>> {
+        Ok(ZipReader {
+            archive: ZipArchive::new(reader)?,
+            passwords,
+            preferred_password: None,
+        })
+    }
+}
+
+impl<R: Read + Seek> ArchiveReader for ZipReader<'_, R> {
+    fn entry_count(&self) -> Option<usize> {
+        Some(self.archive.len())
+    }
+
+    fn for_each_entry(&mut self, visit: &mut EntryVisitor) -> Result<(), Box<dyn Error>> {
+        for index in 0..self.archive.len() {
+            self.visit_entry(index, visit)?;
+        }
+        Ok(())
+    }
+
+    fn file_names(&mut self) -> Result<Option<Vec<String>>, Box<dyn Error>> {
+        let mut names = Vec::new();
+        for index in 0..self.archive.len() {
+            let raw = self.archive.by_index_raw(index)?;
+            if raw.is_file() {
+                names.push(raw.name().to_string());
+            }
+        }
+        Ok(Some(names))
+    }
+
+    fn random_access(&self) -> bool {
+        true
+    }
+
+    fn visit_entry(&mut self, index: usize, visit: &mut EntryVisitor) -> Result<(), Box<dyn Error>> {
+        // Look at the entry's metadata without decrypting or decompressing it.
+        let meta = {
+            let raw = self.archive.by_index_raw(index)?;
+            let extended_mtime = raw.extra_data_fields().find_map(|field| match field {
+                ExtraField::ExtendedTimestamp(timestamp) => timestamp.mod_time(),
+                // Later releases of the zip crate parse other extra fields too.
+                #[allow(unreachable_patterns)]
+                _ => None,
+            });
+            EntryMeta {
+                name: raw.name().to_string(),
+                is_file: raw.is_file(),
+                encrypted: raw.encrypted(),
+                size: Some(raw.size()),
+                compressed_size: raw.compressed_size(),
+                crc32: Some(raw.crc32()),
+                // Prefer the UTC time from an extended timestamp field over the zone-less DOS time.
+                modified: extended_mtime
+                    .and_then(|mtime| OffsetDateTime::from_unix_timestamp(mtime as i64).ok())
+                    .map(EntryTime::Utc)
+                    .or_else(|| {
+                        raw.last_modified()
+                            .and_then(|modified| OffsetDateTime::try_from(modified).ok())
+                            .map(|modified| EntryTime::Dos(PrimitiveDateTime::new(modified.date(), modified.time())))
+                    }),
+                unix_mode: raw.unix_mode(),
+            }
+        };
+        visit(&mut ZipEntry {
+            archive: &mut self.archive,
+            passwords: self.passwords,
+            preferred_password: &mut self.preferred_password,
+            index,
+            meta,
+        })
+    }
+}
+
+/// Whether `password` decrypts entry `index`. The check in the encryption header lets about one
+/// in 256 wrong ZipCrypto passwords through, so the entry is also decompressed, reading no more
+/// than one byte past its declared `size`, and must come out at that size with a matching CRC-32.
+fn decrypts<R: Read + Seek>(archive: &mut ZipArchive<R>, index: usize, size: u64, password: &[u8]) -> bool {
+    let Ok(file) = archive.by_index_decrypt(index, password) else {
+        return false;
+    };
+    // The zip crate checks the CRC-32 once the data has been read to its end.
+    matches!(io::copy(&mut file.take(size.saturating_add(1)), &mut io::sink()), Ok(read) if read == size)
+}
+
+struct ZipEntry<'r, R> {
+    archive: &'r mut ZipArchive<R>,
+    passwords: &'r Passwords,
+    preferred_password: &'r mut Option<usize>,
+    index: usize,
+    meta: EntryMeta,
+}
+
+impl<R: Read + Seek> Entry for ZipEntry<'_, R> {
+    fn meta(&self) -> &EntryMeta {
+        &self.meta
+    }
+
+    fn open(&mut self) -> Result<Opened<'_>, Box<dyn Error>> {
+        let index = self.index;
+        if !self.meta.encrypted {
+            return Ok(Opened::Data(Box::new(self.archive.by_index(index)?)));
+        }
+
+        let archive = &mut *self.archive;
+        let size = self.meta.size.unwrap_or(0);
+        match self
+            .passwords
+            .find(*self.preferred_password, |password| decrypts(archive, index, size, password))
+        {
+            Some(found) => {
+                *self.preferred_password = Some(found);
+                let zip_file = self.archive.by_index_decrypt(index, self.passwords.get(found))?;
+                Ok(Opened::Data(Box::new(zip_file)))
+            }
+            None if self.passwords.is_empty() => Ok(Opened::Undecryptable("no password was given")),
+            None => Ok(Opened::Undecryptable("none of the passwords matched")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::unstable::write::FileOptionsExt;
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    use super::*;
+
+    const CONTENTS: &[u8] = b"the quick brown fox jumps over the lazy dog";
+
+    fn encrypted_zip(password: &str) -> ZipArchive<Cursor<Vec<u8>>> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("secret.txt", SimpleFileOptions::default().with_deprecated_encryption(password.as_bytes()))
+            .unwrap();
+        zip.write_all(CONTENTS).unwrap();
+        ZipArchive::new(Cursor::new(zip.finish().unwrap().into_inner())).unwrap()
+    }
+
+    #[test]
+    fn wrong_passwords_that_pass_the_header_check_are_rejected() {
+        let mut archive = encrypted_zip("right");
+        let size = CONTENTS.len() as u64;
+        assert!(decrypts(&mut archive, 0, size, b"right"));
+
+        // About one in 256 wrong passwords gets past the check in the encryption header.
+        let lucky: Vec<String> = (0..5000)
+            .map(|n| format!("wrong{}", n))
+            .filter(|password| archive.by_index_decrypt(0, password.as_bytes()).is_ok())
+            .collect();
+        assert!(!lucky.is_empty());
+        for password in lucky {
+            assert!(!decrypts(&mut archive, 0, size, password.as_bytes()), "{} decrypts", password);
+        }
+    }
+
+    #[test]
+    fn the_right_password_is_found_among_wrong_ones() {
+        let passwords: Vec<String> = (0..1000).map(|n| format!("wrong{}", n)).chain(["right".to_string()]).collect();
+        let passwords = Passwords::load(&passwords, None, None).unwrap();
+        let mut reader = ZipReader { archive: encrypted_zip("right"), passwords: &passwords, preferred_password: None };
+        let mut contents = Vec::new();
+        reader
+            .for_each_entry(&mut |entry| match entry.open()? {
+                Opened::Data(mut data) => Ok(data.read_to_end(&mut contents).map(drop)?),
+                Opened::Undecryptable(reason) => Err(reason.into()),
+            })
+            .unwrap();
+        assert_eq!(contents, CONTENTS);
+        assert_eq!(reader.preferred_password, Some(1000));
+    }
+}
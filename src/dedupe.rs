@@ -0,0 +1,161 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use clap::ValueEnum;
+
+use crate::hash::ContentHash;
+
+/// What to do with a file whose contents were already extracted in this run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DedupeMode {
+    /// Do not keep the duplicate at all.
+    Skip,
+    /// Replace the duplicate with a hard link to the first copy.
+    Hardlink,
+}
+
+/// Remembers the contents of every file written in a run, to recognise duplicates.
+/// Shared by everything in a run, so it can be used from several threads.
+pub struct Deduplicator {
+    pub mode: DedupeMode,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// The first file written with each content.
+    by_hash: HashMap<ContentHash, PathBuf>,
+    /// The reverse of `by_hash`, to forget a file when its path is written again.
+    by_path: HashMap<PathBuf, ContentHash>,
+    /// Duplicates found so far, by the path of the copy that was kept.
+    groups: BTreeMap<PathBuf, Vec<PathBuf>>,
+}
+
+/// What `Deduplicator::check` found out about a file about to be written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checked {
+    /// An earlier file with the same contents, which the new file duplicates.
+    pub original: Option<PathBuf>,
+    /// Where the file already at the new file's path must be moved before it is overwritten,
+    /// because duplicates of it were left out; that path is then kept in their place.
+    pub move_to: Option<PathBuf>,
+}
+
+impl Deduplicator {
+    pub fn new(mode: DedupeMode) -> Self {
+        Deduplicator {
+            mode,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Records that a file with contents `hash` is about to be written at `path`, or in skip mode
+    /// left out if it turns out to be a duplicate.
+    pub fn check(&self, hash: ContentHash, path: &Path) -> Checked {
+        let mut state = self.state.lock().unwrap();
+
+        let original = state.by_hash.get(&hash).filter(|first| *first != path).cloned();
+        if let Some(original) = &original {
+            state.groups.entry(original.clone()).or_default().push(path.to_path_buf());
+            if self.mode == DedupeMode::Skip {
+                // Nothing is written, so whatever is at this path stays.
+                return Checked { original: Some(original.clone()), move_to: None };
+            }
+        }
+
+        // Whatever is at this path is about to be overwritten.
+        let mut move_to = None;
+        if let Some(old_hash) = state.by_path.remove(path) {
+            if state.by_hash.get(&old_hash).is_some_and(|first| first == path) {
+                state.by_hash.remove(&old_hash);
+                // In skip mode it may be the only copy of the duplicates left out so far, so it is
+                // moved to the first of them that has not been written over since, which is kept
+                // in its place.
+                if self.mode == DedupeMode::Skip && old_hash != hash {
+                    let mut copies = state.groups.remove(path).unwrap_or_default();
+                    copies.retain(|copy| !state.by_path.contains_key(copy));
+                    if !copies.is_empty() {
+                        let first = copies.remove(0);
+                        state.by_hash.insert(old_hash, first.clone());
+                        state.by_path.insert(first.clone(), old_hash);
+                        if !copies.is_empty() {
+                            state.groups.insert(first.clone(), copies);
+                        }
+                        move_to = Some(first);
+                    }
+                }
+            }
+        }
+
+        if original.is_none() {
+            state.by_hash.insert(hash, path.to_path_buf());
+            state.by_path.insert(path.to_path_buf(), hash);
+        }
+        Checked { original, move_to }
+    }
+
+    /// Every group of duplicates found, as the path that was kept and the paths of its duplicates,
+    /// sorted by the kept path.
+    pub fn groups(&self) -> Vec<(PathBuf, Vec<PathBuf>)> {
+        let state = self.state.lock().unwrap();
+        state.groups.iter().map(|(first, duplicates)| (first.clone(), duplicates.clone())).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+
+    use super::*;
+    use crate::hash::HashingWriter;
+
+    fn hash(contents: &str) -> ContentHash {
+        let mut writer = HashingWriter::new(io::sink());
+        writer.write_all(contents.as_bytes()).unwrap();
+        writer.finish().1
+    }
+
+    fn path(name: &str) -> PathBuf {
+        PathBuf::from(name)
+    }
+
+    #[test]
+    fn overwriting_a_kept_file_moves_it_to_a_skipped_duplicate() {
+        let dedupe = Deduplicator::new(DedupeMode::Skip);
+        assert_eq!(dedupe.check(hash("first"), &path("x")), Checked { original: None, move_to: None });
+        assert_eq!(dedupe.check(hash("first"), &path("y")), Checked { original: Some(path("x")), move_to: None });
+        assert_eq!(dedupe.check(hash("first"), &path("z")), Checked { original: Some(path("x")), move_to: None });
+
+        assert_eq!(dedupe.check(hash("second"), &path("x")), Checked { original: None, move_to: Some(path("y")) });
+        assert_eq!(dedupe.groups(), vec![(path("y"), vec![path("z")])]);
+        assert_eq!(dedupe.check(hash("first"), &path("w")), Checked { original: Some(path("y")), move_to: None });
+    }
+
+    #[test]
+    fn duplicates_written_over_since_are_not_moved_to() {
+        let dedupe = Deduplicator::new(DedupeMode::Skip);
+        dedupe.check(hash("first"), &path("x"));
+        dedupe.check(hash("first"), &path("y"));
+        dedupe.check(hash("other"), &path("y"));
+        assert_eq!(dedupe.check(hash("second"), &path("x")), Checked { original: None, move_to: None });
+        assert!(dedupe.groups().is_empty());
+    }
+
+    #[test]
+    fn rewriting_the_same_contents_is_not_a_duplicate() {
+        let dedupe = Deduplicator::new(DedupeMode::Skip);
+        dedupe.check(hash("first"), &path("x"));
+        dedupe.check(hash("first"), &path("y"));
+        assert_eq!(dedupe.check(hash("first"), &path("x")), Checked { original: None, move_to: None });
+        assert_eq!(dedupe.groups(), vec![(path("x"), vec![path("y")])]);
+    }
+
+    #[test]
+    fn hard_links_are_never_moved_to() {
+        let dedupe = Deduplicator::new(DedupeMode::Hardlink);
+        dedupe.check(hash("first"), &path("x"));
+        assert_eq!(dedupe.check(hash("first"), &path("y")), Checked { original: Some(path("x")), move_to: None });
+        assert_eq!(dedupe.check(hash("second"), &path("x")), Checked { original: None, move_to: None });
+    }
+}
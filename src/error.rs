@@ -0,0 +1,185 @@
+use std::error::Error as StdError;
+use std::fmt;
+use std::io;
+
+use crate::limits::LimitExceeded;
+
+/// What went wrong. Each kind has a stable code for scripts and an exit status for the CLI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The options are invalid, e.g. a malformed regular expression or an unreadable password file.
+    InvalidOptions,
+    /// The input could not be read.
+    InputIo,
+    /// The input is not an archive in a supported format.
+    NotAnArchive,
+    /// An encrypted entry or archive could not be decrypted with any of the passwords.
+    Encrypted,
+    /// An entry's data or an archive's structure is corrupt, e.g. a CRC-32 does not match.
+    Corrupt,
+    /// An entry name is unsafe to use as a path.
+    UnsafePath,
+    /// An entry or archive breaks a resource limit.
+    LimitExceeded,
+    /// An output file already exists and the conflict policy does not allow replacing it.
+    OutputExists,
+    /// Writing the output failed.
+    OutputIo,
+}
+
+impl ErrorKind {
+    /// A short identifier, such as "corrupt", that stays the same between releases.
+    pub fn code(self) -> &'static str {
+        match self {
+            ErrorKind::InvalidOptions => "invalid-options",
+            ErrorKind::InputIo => "input-io",
+            ErrorKind::NotAnArchive => "not-an-archive",
+            ErrorKind::Encrypted => "encrypted",
+            ErrorKind::Corrupt => "corrupt",
+            ErrorKind::UnsafePath => "unsafe-path",
+            ErrorKind::LimitExceeded => "limit-exceeded",
+            ErrorKind::OutputExists => "output-exists",
+            ErrorKind::OutputIo => "output-io",
+        }
+    }
+
+    /// The command-line tool's exit status when a run fails with this kind of error.
+    /// Invalid options share status 2 with the usage errors reported by the argument parser.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            ErrorKind::InvalidOptions => 2,
+            ErrorKind::InputIo => 3,
+            ErrorKind::NotAnArchive => 4,
+            ErrorKind::Encrypted => 5,
+            ErrorKind::Corrupt => 6,
+            ErrorKind::UnsafePath => 7,
+            ErrorKind::LimitExceeded => 8,
+            ErrorKind::OutputExists => 9,
+            ErrorKind::OutputIo => 10,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// An error, with the archive and entry it concerns where there is one.
+/// Displayed as the location, e.g. "batch.zip!/inner.zip!/a.png", followed by the message.
+#[derive(Debug, Clone)]
+pub struct Error {
+    kind: ErrorKind,
+    /// The archive, e.g. "batch.zip" or "batch.zip!/inner.zip" when nested.
+    archive: Option<String>,
+    /// The entry name inside that archive, as stored in the archive.
+    entry: Option<String>,
+    message: String,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, message: impl fmt::Display) -> Self {
+        Error {
+            kind,
+            archive: None,
+            entry: None,
+            message: message.to_string(),
+        }
+    }
+
+    /// An error from reading an archive or an entry's data: a broken resource limit, corrupt
+    /// data, or otherwise a failure to read the input.
+    pub(crate) fn reading(error: io::Error) -> Self {
+        let kind = if LimitExceeded::from_io(&error).is_some() {
+            ErrorKind::LimitExceeded
+        } else if matches!(error.kind(), io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof) {
+            ErrorKind::Corrupt
+        } else {
+            ErrorKind::InputIo
+        };
+        Error::new(kind, error)
+    }
+
+    /// Sets the archive the error concerns, unless a more specific one is already set.
+    pub(crate) fn in_archive(mut self, archive: impl fmt::Display) -> Self {
+        self.archive.get_or_insert_with(|| archive.to_string());
+        self
+    }
+
+    /// Sets the entry the error concerns, unless a more specific one is already set.
+    pub(crate) fn at_entry(mut self, entry: &str) -> Self {
+        if self.entry.is_none() && self.archive.is_none() {
+            self.entry = Some(entry.to_string());
+        }
+        self
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The stable code of the error's kind, such as "corrupt".
+    pub fn code(&self) -> &'static str {
+        self.kind.code()
+    }
+
+    /// The archive the error concerns, if any.
+    pub fn archive(&self) -> Option<&str> {
+        self.archive.as_deref()
+    }
+
+    /// The entry the error concerns, if any, as named in the archive.
+    pub fn entry(&self) -> Option<&str> {
+        self.entry.as_deref()
+    }
+
+    /// What went wrong, without the location.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(archive) = &self.archive {
+            f.write_str(archive)?;
+            if self.entry.is_some() {
+                f.write_str("!/")?;
+            }
+        }
+        if let Some(entry) = &self.entry {
+            // Names of unsafe entries may contain control characters; never print them raw.
+            for c in entry.chars() {
+                if c.is_control() {
+                    write!(f, "{}", c.escape_default())?;
+                } else {
+                    write!(f, "{}", c)?;
+                }
+            }
+        }
+        if self.archive.is_some() || self.entry.is_some() {
+            f.write_str(": ")?;
+        }
+        f.write_str(&self.message)
+    }
+}
+
+impl StdError for Error {}
+
+impl From<Box<dyn StdError>> for Error {
+    /// Errors from the archive readers are about the input: they keep their kind if they
+    /// already have one, and are otherwise classified as by `Error::reading`, with anything
+    /// that is not an I/O error taken to mean the archive is corrupt.
+    fn from(error: Box<dyn StdError>) -> Self {
+        let error = match error.downcast::<Error>() {
+            Ok(error) => return *error,
+            Err(error) => error,
+        };
+        match error.downcast::<io::Error>() {
+            Ok(error) => Error::reading(*error),
+            Err(error) => Error::new(ErrorKind::Corrupt, error),
+        }
+    }
+}
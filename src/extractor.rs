@@ -0,0 +1,483 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::thread;
+
+use time::UtcOffset;
+
+use crate::dedupe::{DedupeMode, Deduplicator};
+use crate::error::{Error, ErrorKind};
+use crate::filter::EntryFilter;
+use crate::layout::Layout;
+use crate::limits::Limits;
+use crate::output::{ConflictPolicy, OutputOptions};
+use crate::password::Passwords;
+use crate::process::{self, ExtractOptions, Journal};
+use crate::report::{Report, ReportFormat, Reporter};
+use crate::walk::{self, WalkOptions};
+
+/// What a run does with the entries that match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    /// Write them to the output directory.
+    #[default]
+    Extract,
+    /// Only report them, with their destination paths.
+    List,
+    /// Read them in full to check their integrity, without writing anything.
+    Verify,
+}
+
+/// Extracts the entries of an archive, or of every archive in a directory, that match a set of
+/// filters. Set it up with the builder methods, then call `run`:
+///
+/// ```no_run
+/// use extract_filetype_from_zip::{ConflictPolicy, Extractor};
+///
+/// let report = Extractor::new("photos.zip")
+///     .extensions(["png", "jpg"])
+///     .output("photos")
+///     .on_conflict(ConflictPolicy::Rename)
+///     .run()?;
+/// println!("Extracted {} files", report.summary.extracted);
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+///
+/// Without any filters, every file is extracted. Nothing is printed unless `echo` is used.
+#[derive(Debug, Clone)]
+pub struct Extractor {
+    input: PathBuf,
+    mode: Mode,
+    extensions: Vec<String>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    regex: Option<String>,
+    types: Vec<String>,
+    detect_by_content: bool,
+    walk: WalkOptions,
+    output: Option<PathBuf>,
+    layout: Layout,
+    strip_components: usize,
+    preserve_mtime: bool,
+    preserve_mode: bool,
+    on_conflict: ConflictPolicy,
+    strict: bool,
+    limits: Limits,
+    passwords: Vec<String>,
+    password_file: Option<PathBuf>,
+    password_env: Option<String>,
+    nested_depth: Option<usize>,
+    dedupe: Option<DedupeMode>,
+    jobs: usize,
+    manifest: Option<PathBuf>,
+    echo: Option<ReportFormat>,
+    dos_offset: Option<UtcOffset>,
+}
+
+impl Extractor {
+    /// Reads from `input`, which is either an archive (or single compressed file), recognised by
+    /// its content, or a directory of them, recognised by their suffixes.
+    ///
+    /// Zip times are taken to be in the local time zone, with the offset from UTC in effect at
+    /// each of them, or in UTC if the local time zone cannot be determined; see `dos_offset`.
+    pub fn new(input: impl Into<PathBuf>) -> Self {
+        Extractor {
+            input: input.into(),
+            mode: Mode::Extract,
+            extensions: Vec::new(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            regex: None,
+            types: Vec::new(),
+            detect_by_content: false,
+            walk: WalkOptions::default(),
+            output: None,
+            layout: Layout::flat(),
+            strip_components: 0,
+            preserve_mtime: false,
+            preserve_mode: false,
+            on_conflict: ConflictPolicy::default(),
+            strict: false,
+            limits: Limits::default(),
+            passwords: Vec::new(),
+            password_file: None,
+            password_env: None,
+            nested_depth: None,
+            dedupe: None,
+            jobs: 1,
+            manifest: None,
+            echo: None,
+            dos_offset: None,
+        }
+    }
+
+    /// Whether matching entries are extracted, listed or verified. Defaults to extracting.
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Only match entries with one of these extensions, with or without the dot.
+    pub fn extensions(mut self, extensions: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.extensions.extend(extensions.into_iter().map(Into::into));
+        self
+    }
+
+    /// Only match entries whose full name matches one of these globs.
+    pub fn include(mut self, globs: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.include.extend(globs.into_iter().map(Into::into));
+        self
+    }
+
+    /// Skip entries whose full name matches one of these globs, even if included.
+    pub fn exclude(mut self, globs: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.exclude.extend(globs.into_iter().map(Into::into));
+        self
+    }
+
+    /// Only match entries whose full name matches this regular expression.
+    pub fn regex(mut self, regex: impl Into<String>) -> Self {
+        self.regex = Some(regex.into());
+        self
+    }
+
+    /// Only match entries whose detected content type matches one of these, e.g. "image/png",
+    /// "image/*" or "pdf". Implies `detect_by_content`.
+    pub fn types(mut self, types: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.types.extend(types.into_iter().map(Into::into));
+        self
+    }
+
+    /// Classify entries by their leading bytes instead of their names.
+    pub fn detect_by_content(mut self, detect_by_content: bool) -> Self {
+        self.detect_by_content = detect_by_content;
+        self
+    }
+
+    /// How an input directory is scanned for archives. Only its top level by default.
+    pub fn walk(mut self, walk: WalkOptions) -> Self {
+        self.walk = walk;
+        self
+    }
+
+    /// Directory the files are written under. Required unless verifying.
+    pub fn output(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.output = Some(dir.into());
+        self
+    }
+
+    /// Template for output paths relative to the output directory. Defaults to `Layout::flat`.
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Remove this many leading directories from entry paths before laying them out.
+    /// Entries with no components left are skipped, whatever the layout.
+    pub fn strip_components(mut self, count: usize) -> Self {
+        self.strip_components = count;
+        self
+    }
+
+    /// Give extracted files the modification times recorded in the archive.
+    pub fn preserve_mtime(mut self, preserve_mtime: bool) -> Self {
+        self.preserve_mtime = preserve_mtime;
+        self
+    }
+
+    /// Give extracted files the Unix permissions recorded in the archive.
+    pub fn preserve_mode(mut self, preserve_mode: bool) -> Self {
+        self.preserve_mode = preserve_mode;
+        self
+    }
+
+    /// What to do when an output file already exists. Defaults to overwriting it.
+    pub fn on_conflict(mut self, policy: ConflictPolicy) -> Self {
+        self.on_conflict = policy;
+        self
+    }
+
+    /// Abort an archive at its first unsafe entry name, instead of skipping or renaming the entry.
+    /// Zip and 7z archives are checked before anything is extracted from them; the entries of a
+    /// tar archive before the unsafe one are extracted.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Resource limits on entries and archives. None by default.
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Adds a candidate password for encrypted entries.
+    pub fn password(mut self, password: impl Into<String>) -> Self {
+        self.passwords.push(password.into());
+        self
+    }
+
+    /// Reads further candidate passwords from a file, one per line.
+    pub fn password_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.password_file = Some(path.into());
+        self
+    }
+
+    /// Takes a last candidate password from this environment variable.
+    pub fn password_env(mut self, var: impl Into<String>) -> Self {
+        self.password_env = Some(var.into());
+        self
+    }
+
+    /// Open archives found inside archives, up to `max_depth` levels deep, and filter their
+    /// entries too, instead of treating them as ordinary files.
+    pub fn nested(mut self, max_depth: usize) -> Self {
+        self.nested_depth = Some(max_depth);
+        self
+    }
+
+    /// Recognise files with the same contents as one already extracted in this run.
+    pub fn dedupe(mut self, mode: DedupeMode) -> Self {
+        self.dedupe = Some(mode);
+        self
+    }
+
+    /// Number of archives, or entries of a single zip archive, to process at the same time;
+    /// 0 uses one per CPU core. Defaults to 1.
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs;
+        self
+    }
+
+    /// Also write the records of the extracted files to this file, as a JSON array.
+    pub fn manifest(mut self, path: impl Into<PathBuf>) -> Self {
+        self.manifest = Some(path.into());
+        self
+    }
+
+    /// Print progress and results on standard output, and problems on standard error, as the
+    /// command-line tool does.
+    pub fn echo(mut self, format: ReportFormat) -> Self {
+        self.echo = Some(format);
+        self
+    }
+
+    /// Offset from UTC that zip times, which have no time zone, are taken to be in, instead of
+    /// that of the local time zone at each time.
+    pub fn dos_offset(mut self, offset: UtcOffset) -> Self {
+        self.dos_offset = Some(offset);
+        self
+    }
+
+    /// Processes the input. Problems with individual entries and archives, including a single
+    /// input archive, are collected in the report; an error means the run could not be carried
+    /// out at all, e.g. because of invalid options or an input that does not exist.
+    pub fn run(self) -> Result<Report, Error> {
+        let filter = EntryFilter::new(
+            &self.extensions,
+            &self.include,
+            &self.exclude,
+            self.regex.as_deref(),
+            &self.types,
+            self.detect_by_content,
+        )
+        .map_err(|e| Error::new(ErrorKind::InvalidOptions, e))?;
+        let passwords = Passwords::load(&self.passwords, self.password_file.as_deref(), self.password_env.as_deref())
+            .map_err(|e| Error::new(ErrorKind::InvalidOptions, e))?;
+
+        // Ensure the output directory exists, unless nothing will be written to it.
+        let output_dir = match self.output {
+            Some(output_dir) => output_dir,
+            None if self.mode == Mode::Verify => PathBuf::new(),
+            None => return Err(Error::new(ErrorKind::InvalidOptions, "An output directory is required.")),
+        };
+        if self.mode == Mode::Extract {
+            fs::create_dir_all(&output_dir).map_err(|e| {
+                let message = format!("Cannot create output directory {}: {}", output_dir.display(), e);
+                Error::new(ErrorKind::OutputIo, message)
+            })?;
+        }
+        let options = ExtractOptions {
+            mode: self.mode,
+            filter,
+            output: OutputOptions {
+                dir: output_dir,
+                layout: self.layout,
+                strip_components: self.strip_components,
+                on_conflict: self.on_conflict,
+                preserve_mtime: self.preserve_mtime,
+                preserve_mode: self.preserve_mode,
+                dos_offset: self.dos_offset,
+                claimed: Mutex::default(),
+            },
+            strict: self.strict,
+            limits: self.limits,
+            passwords,
+            nested_depth: self.nested_depth,
+            dedupe: self.dedupe.map(Deduplicator::new),
+            reporter: Reporter::new(self.echo, self.manifest),
+        };
+        let jobs = match self.jobs {
+            0 => thread::available_parallelism().map_or(1, |jobs| jobs.get()),
+            jobs => jobs,
+        };
+
+        // Determine if the input path is a file or a directory.
+        if self.input.is_dir() {
+            // Process all archives in the given directory, descending into subdirectories if asked to.
+            let archives = walk::find_archives(&self.input, &self.walk, &mut |warning| options.reporter.warning(warning))
+                .map_err(|e| Error::new(ErrorKind::InputIo, e).in_archive(self.input.display()))?;
+            if jobs > 1 && archives.len() > 1 {
+                process::process_in_parallel(&archives, jobs, &options);
+            } else {
+                for path in &archives {
+                    options.reporter.archive(path);
+                    if let Err(e) = process::process_archive_file(path, &options, &Journal::immediate(&options), jobs) {
+                        process::report_archive_error(path, e, &options);
+                    }
+                }
+            }
+        } else if self.input.is_file() {
+            // Process a single archive, reporting its failure as for an archive in a directory.
+            options.reporter.archive(&self.input);
+            if let Err(e) = process::process_archive_file(&self.input, &options, &Journal::immediate(&options), jobs) {
+                process::report_archive_error(&self.input, e, &options);
+            }
+        } else {
+            let message = format!("Input path {} is not a valid file or directory.", self.input.display());
+            return Err(Error::new(ErrorKind::InputIo, message));
+        }
+
+        process::finish(&options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::Write;
+    use std::path::Path;
+
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    use super::*;
+
+    fn write_zip(path: &Path, entries: &[(&str, &str)]) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        for (name, contents) in entries {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn overwriting_a_kept_file_keeps_its_skipped_duplicates() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in");
+        let out = dir.path().join("out");
+        fs::create_dir(&input).unwrap();
+        write_zip(&input.join("a.zip"), &[("x.txt", "first contents"), ("y.txt", "first contents")]);
+        write_zip(&input.join("b.zip"), &[("x.txt", "second contents")]);
+
+        let report = Extractor::new(&input).output(&out).dedupe(DedupeMode::Skip).run().unwrap();
+
+        assert_eq!(fs::read_to_string(out.join("x.txt")).unwrap(), "second contents");
+        assert_eq!(fs::read_to_string(out.join("y.txt")).unwrap(), "first contents");
+        assert!(report.files.iter().all(|record| record.duplicate_of.is_none()));
+        assert!(report.duplicates.is_empty());
+        assert_eq!((report.summary.extracted, report.summary.skipped), (3, 0));
+    }
+
+    #[test]
+    fn strict_mode_checks_zip_names_before_extracting_anything() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("a.zip");
+        let out = dir.path().join("out");
+        write_zip(&input, &[("one.png", "one"), ("two.png", "two"), ("../evil.png", "evil")]);
+
+        for jobs in [1, 2] {
+            let report = Extractor::new(&input).output(&out).strict(true).jobs(jobs).run().unwrap();
+            assert_eq!(report.summary.archives_failed, 1);
+            assert_eq!(report.errors[0].kind(), ErrorKind::UnsafePath);
+            assert!(report.files.is_empty());
+            assert_eq!(fs::read_dir(&out).unwrap().count(), 0);
+        }
+    }
+
+    #[test]
+    fn listing_names_hash_suffixed_tar_entries_as_extracting_does() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("a.tar");
+        let out = dir.path().join("out");
+        let mut tar = tar::Builder::new(File::create(&input).unwrap());
+        for (name, contents) in [("a/b.txt", "one"), ("c/b.txt", "two")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            tar.append_data(&mut header, name, contents.as_bytes()).unwrap();
+        }
+        tar.finish().unwrap();
+
+        let run = |mode| Extractor::new(&input).output(&out).on_conflict(ConflictPolicy::HashSuffix).mode(mode).run();
+        let outputs = |report: Report| report.files.into_iter().map(|file| file.output).collect::<Vec<_>>();
+        let listed = outputs(run(Mode::List).unwrap());
+        let extracted = outputs(run(Mode::Extract).unwrap());
+        assert_eq!(listed, extracted);
+        assert!(extracted[1].ends_with(format!("b_{:08x}.txt", crc32fast::hash(b"two"))));
+    }
+
+    #[test]
+    fn stripping_components_skips_short_entries_whatever_the_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("a.zip");
+        let out = dir.path().join("out");
+        write_zip(&input, &[("top.txt", "top"), ("dir/kept.txt", "kept")]);
+
+        let layout = Layout::parse("{ext}/{archive_stem}_{name}").unwrap();
+        let report = Extractor::new(&input).output(&out).layout(layout).strip_components(1).run().unwrap();
+        let outputs: Vec<_> = report.files.into_iter().map(|file| file.output).collect();
+        assert_eq!(outputs, [out.join("txt/a_kept")]);
+        assert_eq!(report.warnings.len(), 1);
+    }
+
+    /// What a run did, with paths relative to `out`, for comparing runs with different `jobs`.
+    fn run_in_order(input: &Path, out: &Path, jobs: usize) -> (Vec<String>, Vec<String>, Vec<String>) {
+        let report = Extractor::new(input).output(out).on_conflict(ConflictPolicy::Rename).jobs(jobs).run().unwrap();
+        let files = report.files.iter().map(|file| {
+            let output = file.output.strip_prefix(out).unwrap().display();
+            format!("{}!/{} -> {}: {}", file.archive, file.entry, output, fs::read_to_string(&file.output).unwrap())
+        });
+        let errors = report.errors.iter().map(|error| error.to_string());
+        (files.collect(), report.warnings, errors.collect())
+    }
+
+    #[test]
+    fn archives_processed_in_parallel_are_replayed_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in");
+        fs::create_dir(&input).unwrap();
+        for n in 0..8 {
+            let contents = format!("archive {}", n);
+            write_zip(&input.join(format!("{}.zip", n)), &[("same.txt", &contents), ("../unsafe.txt", &contents)]);
+        }
+
+        let sequential = run_in_order(&input, &dir.path().join("j1"), 1);
+        assert_eq!((sequential.0.len(), sequential.2.len()), (8, 8));
+        assert_eq!(run_in_order(&input, &dir.path().join("j4"), 4), sequential);
+    }
+
+    #[test]
+    fn entries_processed_in_parallel_are_replayed_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("a.zip");
+        let entries: Vec<_> = (0..40).map(|n| (format!("dir{}/same.txt", n), format!("entry {}", n))).collect();
+        let entries: Vec<_> = entries.iter().map(|(name, contents)| (name.as_str(), contents.as_str())).collect();
+        write_zip(&input, &entries);
+
+        let sequential = run_in_order(&input, &dir.path().join("j1"), 1);
+        assert_eq!(sequential.0.len(), 40);
+        assert_eq!(run_in_order(&input, &dir.path().join("j4"), 4), sequential);
+    }
+}
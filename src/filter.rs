@@ -0,0 +1,150 @@
+use std::error::Error;
+use std::ffi::OsStr;
+use std::path::Path;
+
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use regex::Regex;
+
+use crate::sniff::{self, FileKind};
+
+/// Selection criteria applied to the full name, and optionally the content, of each zip entry.
+/// An entry is selected only if it passes every criterion that was configured.
+#[derive(Debug)]
+pub struct EntryFilter {
+    /// Lower-case extensions without a leading dot. Empty means any extension.
+    extensions: Vec<String>,
+    /// If present, the entry name must match at least one of these globs.
+    include: Option<GlobSet>,
+    /// If present, entries matching any of these globs are rejected.
+    exclude: Option<GlobSet>,
+    /// If present, the entry name must match this regular expression.
+    regex: Option<Regex>,
+    /// `--type` specs the detected content type must match. Empty means any type.
+    types: Vec<String>,
+    /// Whether entries are classified by their leading bytes rather than their names.
+    detect_by_content: bool,
+}
+
+impl EntryFilter {
+    /// Builds a filter from the raw command-line values.
+    /// Globs use `/` as the separator: `*` stays within one directory and `**` spans several.
+    /// Giving any `types` turns on content detection.
+    pub fn new(
+        extensions: &[String],
+        include: &[String],
+        exclude: &[String],
+        regex: Option<&str>,
+        types: &[String],
+        detect_by_content: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        // Prepare the extension filters in lower-case, without a leading dot.
+        let normalized: Vec<String> = extensions
+            .iter()
+            .map(|ext| ext.trim().trim_start_matches('.').to_lowercase())
+            .filter(|ext| !ext.is_empty())
+            .collect();
+        if !extensions.is_empty() && normalized.is_empty() {
+            return Err("At least one non-empty extension must be given.".into());
+        }
+
+        let regex = match regex {
+            Some(pattern) => Some(
+                Regex::new(pattern).map_err(|e| format!("Invalid regex {:?}: {}", pattern, e))?,
+            ),
+            None => None,
+        };
+
+        // Reject type specs that could never match, so typos do not silently extract nothing.
+        for spec in types {
+            if !sniff::ALL_KINDS.iter().any(|kind| kind.matches_spec(spec)) {
+                return Err(format!("Unknown file type {:?}.", spec).into());
+            }
+        }
+
+        Ok(EntryFilter {
+            extensions: normalized,
+            include: build_glob_set(include)?,
+            exclude: build_glob_set(exclude)?,
+            regex,
+            types: types.to_vec(),
+            detect_by_content: detect_by_content || !types.is_empty(),
+        })
+    }
+
+    /// Returns true if the entry's leading bytes must be passed to `matches_content`
+    /// before deciding whether to extract it.
+    pub fn needs_content(&self) -> bool {
+        self.detect_by_content
+    }
+
+    /// Returns true if the entry with the given full name passes the name-based criteria.
+    /// When content detection is off, this is the final decision.
+    pub fn matches_name(&self, entry_name: &str) -> bool {
+        if let Some(exclude) = &self.exclude {
+            if exclude.is_match(entry_name) {
+                return false;
+            }
+        }
+        if let Some(include) = &self.include {
+            if !include.is_match(entry_name) {
+                return false;
+            }
+        }
+        if let Some(regex) = &self.regex {
+            if !regex.is_match(entry_name) {
+                return false;
+            }
+        }
+        self.detect_by_content || self.matches_extension(entry_name)
+    }
+
+    /// Returns true if an entry that passed `matches_name` should be extracted, given the
+    /// kind detected from its leading bytes. Extensions are compared against the detected
+    /// kind, falling back to the entry name when the content is not recognised.
+    pub fn matches_content(&self, entry_name: &str, kind: Option<FileKind>) -> bool {
+        if !self.types.is_empty() {
+            match kind {
+                Some(kind) if self.types.iter().any(|spec| kind.matches_spec(spec)) => {}
+                _ => return false,
+            }
+        }
+        match kind {
+            Some(kind) => {
+                self.extensions.is_empty()
+                    || kind.extensions.iter().any(|ext| self.extensions.iter().any(|e| e == ext))
+            }
+            None => self.matches_extension(entry_name),
+        }
+    }
+
+    /// Checks the entry name's extension against the configured extensions.
+    fn matches_extension(&self, entry_name: &str) -> bool {
+        if self.extensions.is_empty() {
+            return true;
+        }
+        Path::new(entry_name)
+            .extension()
+            .and_then(OsStr::to_str)
+            .map(|entry_ext| self.extensions.contains(&entry_ext.to_lowercase()))
+            .unwrap_or(false)
+    }
+}
+
+/// Compiles the given patterns into a single set, or `None` if there are no patterns.
+fn build_glob_set(patterns: &[String]) -> Result<Option<GlobSet>, Box<dyn Error>> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(compile_glob(pattern)?);
+    }
+    Ok(Some(builder.build()?))
+}
+
+fn compile_glob(pattern: &str) -> Result<Glob, Box<dyn Error>> {
+    GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .map_err(|e| format!("Invalid glob {:?}: {}", pattern, e).into())
+}
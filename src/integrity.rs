@@ -0,0 +1,79 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+
+use crc32fast::Hasher;
+
+/// An entry whose data does not match the CRC-32 recorded in the archive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrcMismatch {
+    pub expected: u32,
+    pub actual: u32,
+}
+
+impl fmt::Display for CrcMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CRC-32 mismatch: archive says {:08x}, data is {:08x}", self.expected, self.actual)
+    }
+}
+
+impl Error for CrcMismatch {}
+
+/// A reader that computes the CRC-32 of an entry's data and fails at the end if it does not
+/// match the one recorded in the archive. It also remembers whether reading the entry failed
+/// for any other reason, such as a decompression error or truncated data, so that corrupt
+/// entries can be told apart from problems writing the output.
+pub struct CrcReader<R> {
+    inner: R,
+    hasher: Hasher,
+    expected: Option<u32>,
+    failed: bool,
+}
+
+impl<R> CrcReader<R> {
+    /// `expected` is the CRC-32 recorded in the archive, if the format has one.
+    pub fn new(inner: R, expected: Option<u32>) -> Self {
+        CrcReader {
+            inner,
+            hasher: Hasher::new(),
+            expected,
+            failed: false,
+        }
+    }
+
+    /// CRC-32 of the data read so far; that of the whole entry once it has been read to the end.
+    pub fn crc32(&self) -> u32 {
+        self.hasher.clone().finalize()
+    }
+
+    /// Returns true if the data could not be read in full or failed its checksum.
+    pub fn failed(&self) -> bool {
+        self.failed
+    }
+}
+
+impl<R: Read> Read for CrcReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.inner.read(buf) {
+            Ok(0) if !buf.is_empty() => {
+                let actual = self.crc32();
+                match self.expected {
+                    Some(expected) if expected != actual => {
+                        self.failed = true;
+                        Err(io::Error::new(io::ErrorKind::InvalidData, CrcMismatch { expected, actual }))
+                    }
+                    _ => Ok(0),
+                }
+            }
+            Ok(n) => {
+                self.hasher.update(&buf[..n]);
+                Ok(n)
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => Err(e),
+            Err(e) => {
+                self.failed = true;
+                Err(e)
+            }
+        }
+    }
+}
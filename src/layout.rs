@@ -0,0 +1,234 @@
+use std::path::{Component, Path, PathBuf};
+
+/// A placeholder that can appear in an output path template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    /// File name of the source archive, e.g. "batch.zip".
+    Archive,
+    /// File name of the source archive without its extension, e.g. "batch".
+    ArchiveStem,
+    /// Directory of the entry inside the archive, after `--strip-components`. May be empty.
+    EntryDir,
+    /// File name of the entry, e.g. "photo.png".
+    FileName,
+    /// File name of the entry without its extension, e.g. "photo".
+    Name,
+    /// Extension of the entry without the dot, e.g. "png". May be empty.
+    Ext,
+    /// Modification date of the entry as YYYY-MM-DD.
+    Date,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Field> {
+        Some(match name {
+            "archive" => Field::Archive,
+            "archive_stem" => Field::ArchiveStem,
+            "entry_dir" => Field::EntryDir,
+            "file_name" => Field::FileName,
+            "name" => Field::Name,
+            "ext" => Field::Ext,
+            "date" => Field::Date,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Field(Field),
+    /// A literal "." immediately followed by `{ext}`, dropped entirely when there is no extension,
+    /// so `{name}.{ext}` renders as just the name for extensionless files.
+    DotExt,
+}
+
+/// Values substituted into a layout for one entry.
+#[derive(Debug, Clone, Default)]
+pub struct LayoutVars<'a> {
+    pub archive: &'a str,
+    pub archive_stem: &'a str,
+    pub entry_dir: &'a str,
+    pub file_name: &'a str,
+    pub name: &'a str,
+    pub ext: &'a str,
+    pub date: &'a str,
+}
+
+/// An output path template such as `{archive_stem}/{entry_dir}/{name}.{ext}`.
+/// Templates are always relative to the output directory and use `/` as the separator;
+/// empty path components (e.g. from an empty `{entry_dir}`) are dropped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    segments: Vec<Segment>,
+}
+
+impl Layout {
+    /// Every file directly in the output directory under its original name.
+    pub fn flat() -> Self {
+        Layout { segments: vec![Segment::Field(Field::FileName)] }
+    }
+
+    /// The entry's directories from inside the archive recreated under the output directory.
+    pub fn preserve_paths() -> Self {
+        Layout {
+            segments: vec![
+                Segment::Field(Field::EntryDir),
+                Segment::Literal("/".to_string()),
+                Segment::Field(Field::FileName),
+            ],
+        }
+    }
+
+    /// Parses a template. `{{` and `}}` stand for literal braces.
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(format!("Unclosed placeholder in layout {:?}", template)),
+                        }
+                    }
+                    let field = Field::parse(&name)
+                        .ok_or_else(|| format!("Unknown placeholder {{{}}} in layout {:?}", name, template))?;
+                    if field == Field::Ext && literal.ends_with('.') {
+                        literal.pop();
+                        flush_literal(&mut segments, &mut literal);
+                        segments.push(Segment::DotExt);
+                    } else {
+                        flush_literal(&mut segments, &mut literal);
+                        segments.push(Segment::Field(field));
+                    }
+                }
+                '}' => return Err(format!("Unmatched '}}' in layout {:?}", template)),
+                _ => literal.push(c),
+            }
+        }
+        flush_literal(&mut segments, &mut literal);
+
+        // The literal parts must not be able to leave the output directory.
+        let literals: String = segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(text) => text.as_str(),
+                _ => "x",
+            })
+            .collect();
+        if literals.starts_with('/') || literals.starts_with('\\') {
+            return Err(format!("Layout {:?} must be relative to the output directory", template));
+        }
+        if literals.split(['/', '\\']).any(|part| part == "..") {
+            return Err(format!("Layout {:?} must not contain '..'", template));
+        }
+        if !segments.iter().any(|segment| matches!(segment, Segment::Field(_))) {
+            return Err(format!("Layout {:?} contains no placeholders, so every file would collide", template));
+        }
+
+        Ok(Layout { segments })
+    }
+
+    /// Renders the layout into a path relative to the output directory.
+    /// Returns `None` if the result has no usable components.
+    pub fn render(&self, vars: &LayoutVars) -> Option<PathBuf> {
+        let mut rendered = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => rendered.push_str(text),
+                Segment::Field(field) => rendered.push_str(match field {
+                    Field::Archive => vars.archive,
+                    Field::ArchiveStem => vars.archive_stem,
+                    Field::EntryDir => vars.entry_dir,
+                    Field::FileName => vars.file_name,
+                    Field::Name => vars.name,
+                    Field::Ext => vars.ext,
+                    Field::Date => vars.date,
+                }),
+                Segment::DotExt if !vars.ext.is_empty() => {
+                    rendered.push('.');
+                    rendered.push_str(vars.ext);
+                }
+                Segment::DotExt => {}
+            }
+        }
+
+        // Keep only plain components so substituted values cannot escape the output directory.
+        let path: PathBuf = rendered
+            .split('/')
+            .map(Path::new)
+            .flat_map(Path::components)
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .collect();
+        if path.as_os_str().is_empty() {
+            None
+        } else {
+            Some(path)
+        }
+    }
+}
+
+fn flush_literal(segments: &mut Vec<Segment>, literal: &mut String) {
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(std::mem::take(literal)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars<'a>(archive: &'a str, archive_stem: &'a str, entry_dir: &'a str, file_name: &'a str) -> LayoutVars<'a> {
+        let (name, ext) = file_name.rsplit_once('.').unwrap_or((file_name, ""));
+        LayoutVars { archive, archive_stem, entry_dir, file_name, name, ext, date: "2024-01-02" }
+    }
+
+    #[test]
+    fn templates_that_leave_the_output_directory_are_rejected() {
+        assert!(Layout::parse("../{file_name}").is_err());
+        assert!(Layout::parse("out/../../{file_name}").is_err());
+        assert!(Layout::parse("{archive_stem}/..\\{file_name}").is_err());
+        assert!(Layout::parse("/tmp/{file_name}").is_err());
+        assert!(Layout::parse("\\{file_name}").is_err());
+        assert!(Layout::parse("out..{name}/{file_name}").is_ok());
+    }
+
+    #[test]
+    fn substituted_values_cannot_leave_the_output_directory() {
+        let layout = Layout::parse("{archive_stem}/{file_name}").unwrap();
+        // `Path::file_stem` of "..zip" is ".", and of "...zip" is "..".
+        assert_eq!(layout.render(&vars("..zip", ".", "", "a.png")), Some(PathBuf::from("a.png")));
+        assert_eq!(layout.render(&vars("...zip", "..", "", "a.png")), Some(PathBuf::from("a.png")));
+
+        let layout = Layout::parse("{archive_stem}/{entry_dir}/{file_name}").unwrap();
+        let rendered = layout.render(&vars("x.zip", "/abs", "../../up", "a.png"));
+        assert_eq!(rendered, Some(PathBuf::from("abs/up/a.png")));
+    }
+
+    #[test]
+    fn empty_renders_are_none() {
+        let layout = Layout::parse("{archive_stem}").unwrap();
+        assert_eq!(layout.render(&vars("..zip", ".", "", "a.png")), None);
+        assert_eq!(layout.render(&vars("x.zip", "", "", "a.png")), None);
+    }
+
+    #[test]
+    fn dot_ext_is_dropped_without_an_extension() {
+        let layout = Layout::parse("{entry_dir}/{name}.{ext}").unwrap();
+        assert_eq!(layout.render(&vars("x.zip", "x", "d", "a.png")), Some(PathBuf::from("d/a.png")));
+        assert_eq!(layout.render(&vars("x.zip", "x", "", "README")), Some(PathBuf::from("README")));
+    }
+}
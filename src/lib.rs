@@ -0,0 +1,33 @@
+//! Extracts files of specific types from zip, 7z and tar archives, compressed tarballs and
+//! single compressed files, either one archive or every archive in a directory.
+//!
+//! Entries are selected by extension, glob, regular expression or detected content type, and
+//! written under an output directory following a layout, with policies for existing files,
+//! unsafe names, resource limits, encryption, nested archives and duplicates. Everything is
+//! driven by an [`Extractor`], which returns a [`Report`] of what happened.
+
+mod archive;
+mod dedupe;
+mod error;
+mod extractor;
+mod filter;
+mod hash;
+mod integrity;
+mod layout;
+mod limits;
+mod output;
+mod password;
+mod process;
+mod report;
+mod sanitize;
+mod sniff;
+mod walk;
+
+pub use dedupe::DedupeMode;
+pub use error::{Error, ErrorKind};
+pub use extractor::{Extractor, Mode};
+pub use layout::Layout;
+pub use limits::{parse_size, Limits};
+pub use output::ConflictPolicy;
+pub use report::{ManifestRecord, Report, ReportFormat, Summary};
+pub use walk::WalkOptions;
@@ -0,0 +1,283 @@
+use std::cell::Cell;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+use std::rc::Rc;
+
+/// Entries that decompress to less than this are never rejected for their compression ratio,
+/// since small, highly repetitive files legitimately compress very well.
+const RATIO_CHECK_MIN_SIZE: u64 = 1024 * 1024;
+
+/// Resource limits that protect against zip bombs and oversized untrusted archives.
+/// Every limit is optional; `None` means unlimited.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+    /// Maximum uncompressed size of a single entry, in bytes.
+    pub max_entry_size: Option<u64>,
+    /// Maximum total uncompressed bytes extracted from one archive.
+    pub max_archive_size: Option<u64>,
+    /// Maximum ratio of uncompressed to compressed size for a single entry, or for compressed
+    /// tarballs, of all the data extracted from the archive.
+    pub max_ratio: Option<f64>,
+    /// Maximum number of entries an archive may contain.
+    pub max_entries: Option<usize>,
+}
+
+/// A limit that an archive or entry went over.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LimitExceeded(String);
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "limit exceeded: {}", self.0)
+    }
+}
+
+impl Error for LimitExceeded {}
+
+impl LimitExceeded {
+    /// Returns the limit violation wrapped in `error`, if that is what it is.
+    pub fn from_io(error: &io::Error) -> Option<&LimitExceeded> {
+        error.get_ref().and_then(|inner| inner.downcast_ref::<LimitExceeded>())
+    }
+}
+
+impl Limits {
+    /// Checks the number of entries in an archive before any of them are read.
+    pub fn check_entry_count(&self, count: usize) -> Result<(), LimitExceeded> {
+        match self.max_entries {
+            Some(max) if count > max => Err(LimitExceeded(format!(
+                "archive has {} entries, more than the maximum of {}",
+                count, max
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks the sizes an entry declares in the archive's metadata.
+    /// `archive_total` is the number of bytes already extracted from the same archive.
+    pub fn check_declared(&self, size: u64, compressed_size: u64, archive_total: u64) -> Result<(), LimitExceeded> {
+        self.check("declared size", size, compressed_size, archive_total)
+    }
+
+    /// Wraps an entry's decompressed stream so that the limits are enforced on the bytes
+    /// actually produced, whatever the metadata claimed.
+    pub fn reader<R: Read>(&self, inner: R, compressed_size: u64, archive_total: u64) -> LimitedReader<R> {
+        LimitedReader {
+            inner,
+            limits: *self,
+            compressed_size,
+            compressed_count: None,
+            archive_total,
+            read: 0,
+        }
+    }
+
+    /// Like `reader`, for an entry of an archive that is compressed as a whole, such as a
+    /// compressed tarball, where `compressed` counts the compressed bytes read so far. How much
+    /// of them each entry takes up is not known, so the compression ratio checked is that of
+    /// all the data extracted from the archive.
+    pub fn counted_reader<R: Read>(&self, inner: R, compressed: ReadCount, archive_total: u64) -> LimitedReader<R> {
+        LimitedReader {
+            compressed_count: Some(compressed),
+            ..self.reader(inner, 0, archive_total)
+        }
+    }
+
+    /// Checks one entry's size; `what` describes where the size came from.
+    fn check(&self, what: &str, size: u64, compressed_size: u64, archive_total: u64) -> Result<(), LimitExceeded> {
+        self.check_size(what, size, archive_total)?;
+        self.check_ratio(what, size, compressed_size)
+    }
+
+    /// Checks one entry's size against the per-entry and per-archive maximums.
+    fn check_size(&self, what: &str, size: u64, archive_total: u64) -> Result<(), LimitExceeded> {
+        if let Some(max) = self.max_entry_size {
+            if size > max {
+                return Err(LimitExceeded(format!(
+                    "{} of {} bytes is over the per-entry maximum of {}",
+                    what, size, max
+                )));
+            }
+        }
+        if let Some(max) = self.max_archive_size {
+            if archive_total.saturating_add(size) > max {
+                return Err(LimitExceeded(format!(
+                    "{} of {} bytes would bring the archive total over the maximum of {}",
+                    what, size, max
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the ratio of `size` bytes of data to the `compressed_size` bytes it came from.
+    fn check_ratio(&self, what: &str, size: u64, compressed_size: u64) -> Result<(), LimitExceeded> {
+        if let Some(max) = self.max_ratio {
+            if size >= RATIO_CHECK_MIN_SIZE {
+                let ratio = size as f64 / compressed_size.max(1) as f64;
+                if ratio > max {
+                    return Err(LimitExceeded(format!(
+                        "{} gives a compression ratio of {:.1}, over the maximum of {}",
+                        what, ratio, max
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A reader that fails with `LimitExceeded` as soon as the data read breaks a limit.
+pub struct LimitedReader<R> {
+    inner: R,
+    limits: Limits,
+    compressed_size: u64,
+    /// For `counted_reader`, the count of compressed bytes read from the archive.
+    compressed_count: Option<ReadCount>,
+    archive_total: u64,
+    read: u64,
+}
+
+impl<R> LimitedReader<R> {
+    /// Number of bytes read so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.read
+    }
+
+    /// Returns the wrapped reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+        match &self.compressed_count {
+            Some(count) => self.limits.check_size("decompressed size", self.read, self.archive_total).and_then(|()| {
+                let total = self.archive_total + self.read;
+                self.limits.check_ratio("data extracted from the archive so far", total, count.get())
+            }),
+            None => self.limits.check("decompressed size", self.read, self.compressed_size, self.archive_total),
+        }
+        .map_err(io::Error::other)?;
+        Ok(n)
+    }
+}
+
+/// The number of bytes read through a `CountingReader` so far, shared with its readers.
+#[derive(Debug, Clone, Default)]
+pub struct ReadCount(Rc<Cell<u64>>);
+
+impl ReadCount {
+    pub fn get(&self) -> u64 {
+        self.0.get()
+    }
+}
+
+/// A reader that counts the bytes read through it, such as the compressed data of a tarball,
+/// since the tar format does not record how much of it each entry takes up.
+pub struct CountingReader<R> {
+    inner: R,
+    count: ReadCount,
+}
+
+impl<R> CountingReader<R> {
+    /// Returns the reader and the count it adds to.
+    pub fn new(inner: R) -> (Self, ReadCount) {
+        let count = ReadCount::default();
+        (CountingReader { inner, count: count.clone() }, count)
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.0.set(self.count.0.get() + n as u64);
+        Ok(n)
+    }
+}
+
+/// Parses a byte size such as "512", "64K", "10MB" or "2GiB" (powers of 1024).
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (digits, unit) = value.split_at(split);
+    let number: u64 = digits
+        .parse()
+        .map_err(|_| format!("invalid size {:?}: expected a number with an optional K, M, G or T suffix", value))?;
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        _ => return Err(format!("invalid size unit in {:?}", value)),
+    };
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size {:?} is too large", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    const MIB: usize = 1024 * 1024;
+
+    #[test]
+    fn counted_ratio_is_that_of_the_archive_so_far() {
+        let limits = Limits { max_ratio: Some(100.0), ..Limits::default() };
+        let (mut compressed, count) = CountingReader::new(Cursor::new(vec![0; 20 * 1024]));
+        io::copy(&mut compressed, &mut io::sink()).unwrap();
+        assert_eq!(count.get(), 20 * 1024);
+
+        let mut reader = limits.counted_reader(Cursor::new(vec![0; MIB]), count.clone(), 0);
+        assert!(io::copy(&mut reader, &mut io::sink()).is_ok());
+        let mut reader = limits.counted_reader(Cursor::new(vec![0; MIB]), count, MIB as u64);
+        let error = io::copy(&mut reader, &mut io::sink()).unwrap_err();
+        assert!(LimitExceeded::from_io(&error).is_some());
+    }
+
+    #[test]
+    fn reader_passes_data_within_the_limits_through() {
+        let limits = Limits { max_entry_size: Some(MIB as u64), max_ratio: Some(10.0), ..Limits::default() };
+        let data: Vec<u8> = (0..MIB).map(|n| n as u8).collect();
+        let mut reader = limits.reader(Cursor::new(data.clone()), MIB as u64 / 2, 0);
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read, data);
+        assert_eq!(reader.bytes_read(), MIB as u64);
+    }
+
+    #[test]
+    fn reader_fails_once_the_data_breaks_a_limit() {
+        let over = |limits: Limits, compressed_size: u64, archive_total: u64| {
+            let mut reader = limits.reader(Cursor::new(vec![0; 2 * MIB]), compressed_size, archive_total);
+            let error = io::copy(&mut reader, &mut io::sink()).unwrap_err();
+            assert!(LimitExceeded::from_io(&error).is_some(), "{}", error);
+            assert!(reader.bytes_read() <= MIB as u64 + 64 * 1024);
+        };
+        over(Limits { max_entry_size: Some(MIB as u64), ..Limits::default() }, 2 * MIB as u64, 0);
+        over(Limits { max_archive_size: Some(3 * MIB as u64), ..Limits::default() }, 2 * MIB as u64, 2 * MIB as u64);
+        over(Limits { max_ratio: Some(100.0), ..Limits::default() }, 10 * 1024, 0);
+    }
+
+    #[test]
+    fn sizes_parse_with_binary_units() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size(" 64K "), Ok(64 << 10));
+        assert_eq!(parse_size("10MB"), Ok(10 << 20));
+        assert_eq!(parse_size("2GiB"), Ok(2 << 30));
+        assert_eq!(parse_size("1 t"), Ok(1 << 40));
+        assert!(parse_size("").is_err());
+        assert!(parse_size("1.5G").is_err());
+        assert!(parse_size("12Q").is_err());
+        assert!(parse_size("-1").is_err());
+        assert!(parse_size("16777216T").is_err());
+    }
+}
@@ -1,108 +1,322 @@
-use std::error::Error;
-use std::ffi::OsStr;
-use std::fs::{self, File};
-use std::io::{self};
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
+use std::process::ExitCode;
 
-use clap::Parser;
-use zip::read::ZipArchive;
+use clap::error::ErrorKind as ClapErrorKind;
+use clap::{ArgGroup, CommandFactory, Parser, Subcommand};
 
-/// Simple program to extract files of a specific type from zip files.
+use extract_filetype_from_zip::{
+    parse_size, ConflictPolicy, DedupeMode, ErrorKind, Extractor, Layout, Limits, Mode, ReportFormat, WalkOptions,
+};
+
+/// Exit status of a run in which some entries failed, but others were extracted.
+const EXIT_PARTIAL_FAILURE: u8 = 1;
+/// Exit status of a run in which nothing matched, with --fail-on-empty.
+const EXIT_NOTHING_MATCHED: u8 = 11;
+
+/// Printed after the options in `--help`; must match `ErrorKind::code` and `ErrorKind::exit_code`.
+const EXIT_STATUS_HELP: &str = "\
+A summary of what was scanned, matched, extracted, skipped and failed is printed at the end.
+Errors are printed on standard error as \"Error [code] location: message\". The exit status is:
+  0  success, including when nothing matched
+  1  partial failure: some entries or archives failed, but other entries were extracted
+ 11  nothing matched, with --fail-on-empty
+When nothing could be extracted because of errors, it is that of the first error:
+  2  invalid-options   the arguments are invalid
+  3  input-io          the input could not be read
+  4  not-an-archive    the input is not a supported archive
+  5  encrypted         an entry or archive could not be decrypted
+  6  corrupt           an entry or archive is corrupt, e.g. its CRC-32 does not match
+  7  unsafe-path       an entry name is unsafe to use as a path
+  8  limit-exceeded    an entry or archive breaks a resource limit
+  9  output-exists     an output file already exists
+ 10  output-io         the output could not be written";
+
+/// Simple program to extract files of a specific type from zip, 7z and tar archives.
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, after_help = EXIT_STATUS_HELP)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    args: Option<Args>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Show what would be extracted, with each entry's sizes, modification time, CRC and
+    /// destination path, without writing anything to the output directory.
+    List(Args),
+    /// Read every matching entry and check it against the CRC-32 recorded in the archive,
+    /// without writing anything. Fails if any entry or archive is corrupt.
+    Verify(Args),
+}
+
+#[derive(clap::Args, Debug)]
+#[command(group = ArgGroup::new("structured").args(["preserve_paths", "layout"]))]
 struct Args {
-    /// Path to a zip file or a directory containing zip files.
+    /// Path to an archive or a directory containing archives. Zip, 7z and tar archives are
+    /// supported, including gzip, bzip2, xz and zstd compressed tarballs, as are single
+    /// compressed files such as "report.csv.gz", which are filtered by their inner name.
+    /// A single input is recognised by its content; in a directory, only files with archive
+    /// or compression suffixes (.zip, .7z, .tar, .tgz, .gz, .bz2, .xz, .zst, ...) are picked up.
+    /// Only the top level of a directory is scanned unless --recursive is given.
     #[arg(short, long, value_name = "INPUT")]
     input: PathBuf,
 
-    /// File extension to filter for (e.g., "txt" or "png"). You may omit the dot.
-    #[arg(short, long, value_name = "EXTENSION")]
-    extension: String,
+    /// File extension(s) to filter for (e.g., "txt" or "png"). You may omit the dot.
+    /// Repeat the flag or separate values with commas to match several, e.g. "png,jpg".
+    #[arg(
+        short,
+        long,
+        value_name = "EXTENSION",
+        value_delimiter = ',',
+        required_unless_present_any = ["include", "regex", "file_type"]
+    )]
+    extension: Vec<String>,
+
+    /// Only extract entries whose full name matches this glob (e.g. "reports/**/2024-*.csv").
+    /// May be repeated; an entry matching any of the patterns is included.
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// Skip entries whose full name matches this glob. May be repeated. Takes precedence over --include.
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Only extract entries whose full name matches this regular expression.
+    #[arg(long, value_name = "REGEX")]
+    regex: Option<String>,
+
+    /// Classify entries by their leading bytes (magic numbers) instead of trusting their names.
+    /// Extensions given with --extension are then compared against the detected type.
+    #[arg(long)]
+    detect_by_content: bool,
+
+    /// Only extract entries whose detected content type matches, e.g. "image/png", "image/*"
+    /// or "pdf". May be repeated or comma-separated. Implies --detect-by-content.
+    #[arg(long = "type", value_name = "TYPE", value_delimiter = ',')]
+    file_type: Vec<String>,
 
-    /// Output directory where the extracted files will be saved.
+    /// Scan subdirectories of an input directory as well.
+    #[arg(short, long)]
+    recursive: bool,
+
+    /// Maximum number of directory levels to descend below the input directory.
+    #[arg(long, value_name = "DEPTH", requires = "recursive")]
+    max_depth: Option<usize>,
+
+    /// Descend into symlinked directories while scanning. Symlink loops are detected and skipped.
+    #[arg(long, requires = "recursive")]
+    follow_symlinks: bool,
+
+    /// Also descend into hidden directories (names starting with a dot), which are skipped by default.
+    #[arg(long, requires = "recursive")]
+    hidden: bool,
+
+    /// Output directory where the extracted files will be saved. Not needed for `verify`.
     #[arg(short, long, value_name = "OUTPUT")]
-    output: PathBuf,
+    output: Option<PathBuf>,
+
+    /// Recreate each entry's directories from inside the archive under the output directory,
+    /// instead of writing every file directly into it.
+    #[arg(long)]
+    preserve_paths: bool,
+
+    /// Template for output paths relative to the output directory, e.g.
+    /// "{archive_stem}/{entry_dir}/{name}.{ext}" or "{ext}/{archive_stem}_{name}".
+    /// Placeholders: {archive}, {archive_stem}, {entry_dir}, {file_name}, {name}, {ext}, {date}.
+    #[arg(long, value_name = "TEMPLATE", value_parser = Layout::parse)]
+    layout: Option<Layout>,
+
+    /// Remove this many leading directories from entry paths before writing (like tar).
+    /// Entries with no components left are skipped.
+    #[arg(long, value_name = "N", default_value_t = 0, requires = "structured")]
+    strip_components: usize,
+
+    /// Set each extracted file's modification time to the one recorded in the archive.
+    /// Zip times carry no time zone and are taken as local time, as zip tools write them,
+    /// unless the entry also has an extended timestamp, which is in UTC.
+    #[arg(long)]
+    preserve_mtime: bool,
+
+    /// Give each extracted file the Unix permissions recorded in the archive, such as executable
+    /// bits. Setuid, setgid and sticky bits are never applied. Has no effect on other platforms.
+    #[arg(long)]
+    preserve_mode: bool,
+
+    /// What to do when an output file already exists, including one written earlier in this run.
+    #[arg(long, value_enum, value_name = "POLICY", default_value_t = ConflictPolicy::Overwrite)]
+    on_conflict: ConflictPolicy,
+
+    /// Abort an archive as soon as it contains an unsafe entry name, instead of skipping or
+    /// rewriting that entry and continuing. The names in zip and 7z archives are all checked
+    /// before anything is extracted; tar archives are read as a stream, so the entries before
+    /// an unsafe one are extracted.
+    #[arg(long)]
+    strict: bool,
+
+    /// Maximum uncompressed size of a single entry (e.g. "512M"). Larger entries are skipped.
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_entry_size: Option<u64>,
+
+    /// Maximum total uncompressed bytes extracted from one archive (e.g. "4G").
+    /// Entries that would go over it are skipped.
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    max_archive_size: Option<u64>,
+
+    /// Maximum ratio of uncompressed to compressed size for an entry, e.g. 100.
+    /// Entries under 1 MiB are exempt. For compressed tarballs, which do not record each entry's
+    /// compressed size, the ratio of everything extracted so far is checked instead.
+    #[arg(long, value_name = "RATIO")]
+    max_ratio: Option<f64>,
+
+    /// Maximum number of entries an archive may contain. Larger archives are not processed at all.
+    #[arg(long, value_name = "N")]
+    max_entries: Option<usize>,
+
+    /// Password for encrypted entries (ZipCrypto or AES). May be repeated to give several
+    /// candidates, which are tried in turn for each encrypted entry.
+    #[arg(long, value_name = "PASSWORD")]
+    password: Vec<String>,
+
+    /// File with candidate passwords, one per line, tried after any --password values.
+    #[arg(long, value_name = "FILE")]
+    password_file: Option<PathBuf>,
+
+    /// Name of an environment variable holding a candidate password, tried last.
+    #[arg(long, value_name = "VAR")]
+    password_env: Option<String>,
+
+    /// Open archives found inside archives and apply the same filters to their entries,
+    /// instead of treating them as ordinary files.
+    #[arg(long)]
+    nested: bool,
+
+    /// Maximum number of archive levels to descend into with --nested.
+    #[arg(long, value_name = "DEPTH", default_value_t = 5, requires = "nested")]
+    max_nested_depth: usize,
+
+    /// Do not write files whose contents are identical (by SHA-256) to a file already extracted
+    /// in this run; with "hardlink", link them to that file instead. Duplicates are listed at the end.
+    #[arg(long, value_enum, value_name = "MODE", num_args = 0..=1, default_missing_value = "skip")]
+    dedupe: Option<DedupeMode>,
+
+    /// Number of archives to process at the same time when the input is a directory, or of entries
+    /// to decompress at the same time when there is a single zip archive; 0 uses one per CPU core.
+    /// Files are still moved into place, and name collisions resolved, in the same order as when
+    /// processing one at a time. The entries of one archive are processed one at a time with
+    /// --max-archive-size, as its running total depends on their order.
+    #[arg(short, long, value_name = "N", default_value_t = 1)]
+    jobs: usize,
+
+    /// Write a JSON manifest of every extracted file (source archive, entry, output path, sizes,
+    /// CRC-32, SHA-256, times and warnings) to this file at the end of the run.
+    #[arg(long, value_name = "FILE")]
+    manifest: Option<PathBuf>,
+
+    /// How results are printed on standard output.
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = ReportFormat::Text)]
+    format: ReportFormat,
+
+    /// Exit with status 11 if no entry matched the filters, instead of succeeding.
+    #[arg(long)]
+    fail_on_empty: bool,
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
+fn main() -> ExitCode {
     // Parse command-line arguments.
-    let args = Args::parse();
-
-    // Ensure the output directory exists.
-    fs::create_dir_all(&args.output)?;
-
-    // Prepare the extension filter in lower-case, without a leading dot.
-    let filter_ext = args.extension.trim_start_matches('.').to_lowercase();
-
-    // Determine if the input path is a file or a directory.
-    if args.input.is_dir() {
-        // Process all .zip files in the given directory (non-recursive).
-        for entry in fs::read_dir(&args.input)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_file()
-                && path
-                    .extension()
-                    .and_then(OsStr::to_str)
-                    .map(|s| s.eq_ignore_ascii_case("zip"))
-                    .unwrap_or(false)
-            {
-                println!("Processing zip file: {}", path.display());
-                if let Err(e) = process_zip_file(&path, &filter_ext, &args.output) {
-                    eprintln!("Error processing {}: {}", path.display(), e);
-                }
-            }
-        }
-    } else if args.input.is_file() {
-        // Process a single zip file.
-        println!("Processing zip file: {}", args.input.display());
-        process_zip_file(&args.input, &filter_ext, &args.output)?;
-    } else {
-        return Err(format!("Input path {} is not a valid file or directory.", args.input.display()).into());
+    let cli = Cli::parse();
+    let (mode, args) = match cli.command {
+        Some(Command::List(args)) => (Mode::List, args),
+        Some(Command::Verify(args)) => (Mode::Verify, args),
+        None => (Mode::Extract, cli.args.expect("clap requires the arguments without a subcommand")),
+    };
+    if args.output.is_none() && mode != Mode::Verify {
+        Cli::command()
+            .error(ClapErrorKind::MissingRequiredArgument, "the argument '--output <OUTPUT>' is required")
+            .exit();
     }
 
-    Ok(())
-}
+    let fail_on_empty = args.fail_on_empty;
+    let mut extractor = Extractor::new(args.input)
+        .mode(mode)
+        .extensions(args.extension)
+        .include(args.include)
+        .exclude(args.exclude)
+        .types(args.file_type)
+        .detect_by_content(args.detect_by_content)
+        .walk(WalkOptions {
+            recursive: args.recursive,
+            max_depth: args.max_depth,
+            follow_symlinks: args.follow_symlinks,
+            include_hidden: args.hidden,
+        })
+        .strip_components(args.strip_components)
+        .preserve_mtime(args.preserve_mtime)
+        .preserve_mode(args.preserve_mode)
+        .on_conflict(args.on_conflict)
+        .strict(args.strict)
+        .limits(Limits {
+            max_entry_size: args.max_entry_size,
+            max_archive_size: args.max_archive_size,
+            max_ratio: args.max_ratio,
+            max_entries: args.max_entries,
+        })
+        .jobs(args.jobs)
+        .echo(args.format);
+    for password in args.password {
+        extractor = extractor.password(password);
+    }
+    if let Some(regex) = args.regex {
+        extractor = extractor.regex(regex);
+    }
+    if let Some(output) = args.output {
+        extractor = extractor.output(output);
+    }
+    match args.layout {
+        Some(layout) => extractor = extractor.layout(layout),
+        None if args.preserve_paths => extractor = extractor.layout(Layout::preserve_paths()),
+        None => {}
+    }
+    if let Some(path) = args.password_file {
+        extractor = extractor.password_file(path);
+    }
+    if let Some(var) = args.password_env {
+        extractor = extractor.password_env(var);
+    }
+    if args.nested {
+        extractor = extractor.nested(args.max_nested_depth);
+    }
+    if let Some(mode) = args.dedupe {
+        extractor = extractor.dedupe(mode);
+    }
+    if let Some(path) = args.manifest {
+        extractor = extractor.manifest(path);
+    }
 
-/// Processes a single zip file by extracting all files that match the given extension.
-/// Files whose names include "__MACOSX" are skipped.
-/// The extracted files are saved to `output_dir` using their original file names.
-/// Note: if multiple files share the same name, later files will overwrite earlier ones.
-fn process_zip_file(zip_path: &Path, ext: &str, output_dir: &Path) -> Result<(), Box<dyn Error>> {
-    let file = File::open(zip_path)?;
-    let mut archive = ZipArchive::new(file)?;
-
-    for i in 0..archive.len() {
-        let mut zip_file = archive.by_index(i)?;
-        let entry_name = zip_file.name();
-
-        // Skip any entry that is part of the "__MACOSX" metadata.
-        if entry_name.contains("__MACOSX") {
-            continue;
+    let report = match extractor.run() {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Error [{}] {}", e.code(), e);
+            return ExitCode::from(e.kind().exit_code());
         }
-
-        // Only process file entries (skip directories).
-        if zip_file.is_file() {
-            let entry_path = Path::new(entry_name);
-
-            // Check if the file's extension matches the desired filter.
-            if let Some(entry_ext) = entry_path.extension().and_then(OsStr::to_str) {
-                if entry_ext.to_lowercase() == ext {
-                    // Get the original file name (the last component of the path).
-                    if let Some(file_name) = entry_path.file_name() {
-                        let output_file_path = output_dir.join(file_name);
-
-                        // Create and write the output file.
-                        let mut outfile = File::create(&output_file_path)?;
-                        io::copy(&mut zip_file, &mut outfile)?;
-                        println!("Extracted: {}", output_file_path.display());
-                    } else {
-                        eprintln!("Warning: Skipping an entry with no valid file name: {}", entry_name);
-                    }
-                }
-            }
+    };
+    let corrupt = report.errors.iter().filter(|e| e.kind() == ErrorKind::Corrupt).count();
+    if mode == Mode::Verify && corrupt > 0 {
+        eprintln!("Verification failed: {} corrupt entries or archives", corrupt);
+    }
+    // Failed entries and archives do not stop the run, but do fail it: partly if other entries
+    // were still dealt with, and with the first error's status if none were.
+    let summary = report.summary;
+    match report.errors.first() {
+        Some(_) if summary.extracted + summary.skipped > 0 => ExitCode::from(EXIT_PARTIAL_FAILURE),
+        Some(e) => ExitCode::from(e.kind().exit_code()),
+        None if summary.matched == 0 && fail_on_empty => {
+            eprintln!("Nothing matched");
+            ExitCode::from(EXIT_NOTHING_MATCHED)
         }
+        None => ExitCode::SUCCESS,
     }
-
-    Ok(())
 }
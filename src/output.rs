@@ -0,0 +1,228 @@
+use std::collections::HashSet;
+use std::ffi::{OsStr, OsString};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use clap::ValueEnum;
+use tempfile::{NamedTempFile, TempPath};
+use time::UtcOffset;
+
+use crate::archive::EntryTime;
+use crate::error::{Error, ErrorKind};
+use crate::layout::{Layout, LayoutVars};
+
+/// What to do when an output file already exists, whether it was written earlier in this run
+/// (by the same or another archive) or was already there beforehand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ConflictPolicy {
+    /// Replace the existing file.
+    #[default]
+    Overwrite,
+    /// Keep the existing file and do not extract the new one.
+    Skip,
+    /// Write the new file as `name_1.ext`, `name_2.ext`, ... using the first free name.
+    Rename,
+    /// Write the new file as `name_<crc32>.ext`, falling back to numbering if that exists too.
+    HashSuffix,
+    /// Stop processing the current archive with an error.
+    Error,
+}
+
+/// Decides where each extracted entry is written.
+#[derive(Debug)]
+pub struct OutputOptions {
+    /// Directory all extracted files are written under.
+    pub dir: PathBuf,
+    /// Template for each file's path relative to `dir`.
+    pub layout: Layout,
+    /// Number of leading path components to drop from entry names before filling in `{entry_dir}`.
+    pub strip_components: usize,
+    /// How to handle an output path that already exists.
+    pub on_conflict: ConflictPolicy,
+    /// Give extracted files the modification times recorded in the archive.
+    pub preserve_mtime: bool,
+    /// Give extracted files the Unix permissions recorded in the archive.
+    pub preserve_mode: bool,
+    /// Offset from UTC that zone-less DOS times are assumed to be in, or `None` for the local
+    /// time zone's offset at each time.
+    pub dos_offset: Option<UtcOffset>,
+    /// Paths given out by `claim` when listing, which count as existing although nothing is
+    /// written to them. Empty to begin with.
+    pub claimed: Mutex<HashSet<PathBuf>>,
+}
+
+impl OutputOptions {
+    /// Returns the path, relative to `dir`, that the entry should be written to.
+    /// `entry_name` must already have been through `sanitize_entry_name`.
+    /// On `Err`, the entry should be skipped and the message reported.
+    pub fn relative_path(&self, archive_path: &Path, entry_name: &str, modified: Option<EntryTime>) -> Result<PathBuf, String> {
+        // Get the original file name (the last component of the path).
+        let file_name = Path::new(entry_name)
+            .file_name()
+            .and_then(OsStr::to_str)
+            .ok_or_else(|| format!("Skipping an entry with no valid file name: {}", entry_name))?;
+
+        // Drop the requested number of leading directories, like `tar --strip-components`, skipping
+        // entries with none left whatever the layout, even if it has no `{entry_dir}`.
+        let components: Vec<&str> = entry_name.split('/').skip(self.strip_components).collect();
+        if components.is_empty() {
+            return Err(format!(
+                "Skipping an entry with fewer than {} path components: {}",
+                self.strip_components + 1,
+                entry_name
+            ));
+        }
+        let entry_dir = components[..components.len() - 1].join("/");
+
+        let file_path = Path::new(file_name);
+        let archive_path_name = archive_path.file_name().and_then(OsStr::to_str).unwrap_or_default();
+        let date = match modified {
+            Some(modified) => {
+                let date = modified.date();
+                format!("{:04}-{:02}-{:02}", date.year(), u8::from(date.month()), date.day())
+            }
+            None => "unknown-date".to_string(),
+        };
+        let vars = LayoutVars {
+            archive: archive_path_name,
+            archive_stem: archive_path.file_stem().and_then(OsStr::to_str).unwrap_or_default(),
+            entry_dir: &entry_dir,
+            file_name,
+            name: file_path.file_stem().and_then(OsStr::to_str).unwrap_or(file_name),
+            ext: file_path.extension().and_then(OsStr::to_str).unwrap_or_default(),
+            date: &date,
+        };
+        self.layout
+            .render(&vars)
+            .ok_or_else(|| format!("Skipping an entry whose output path would be empty: {}", entry_name))
+    }
+
+    /// Applies the conflict policy to `path`, returning the path to write to, or `None` if
+    /// the entry should be skipped. `crc32` is the entry's checksum, used by `HashSuffix`.
+    pub fn resolve_conflict(&self, path: PathBuf, crc32: Option<u32>) -> Result<Option<PathBuf>, Error> {
+        if !self.taken(&path) {
+            return Ok(Some(path));
+        }
+        match self.on_conflict {
+            ConflictPolicy::Overwrite => Ok(Some(path)),
+            ConflictPolicy::Skip => Ok(None),
+            ConflictPolicy::Error => Err(already_exists(&path)),
+            ConflictPolicy::Rename => Ok(Some(self.first_free_path(&path, ""))),
+            ConflictPolicy::HashSuffix => {
+                let Some(crc32) = crc32 else {
+                    return Ok(Some(self.first_free_path(&path, "")));
+                };
+                let suffix = format!("_{:08x}", crc32);
+                let hashed = with_suffix(&path, &suffix);
+                if self.taken(&hashed) {
+                    Ok(Some(self.first_free_path(&path, &suffix)))
+                } else {
+                    Ok(Some(hashed))
+                }
+            }
+        }
+    }
+
+    /// Marks `path` as taken by a listed file, so that `resolve_conflict` treats the files
+    /// listed after it as extracting would.
+    pub fn claim(&self, path: PathBuf) {
+        self.claimed.lock().unwrap().insert(path);
+    }
+
+    /// Whether a file exists at `path`, or would by now if the listed files had been extracted.
+    fn taken(&self, path: &Path) -> bool {
+        path.exists() || self.claimed.lock().unwrap().contains(path)
+    }
+
+    /// Returns the first of `name{suffix}_1.ext`, `name{suffix}_2.ext`, ... that is not taken.
+    fn first_free_path(&self, path: &Path, suffix: &str) -> PathBuf {
+        (1u64..)
+            .map(|n| with_suffix(path, &format!("{}_{}", suffix, n)))
+            .find(|candidate| !self.taken(candidate))
+            .expect("ran out of candidate file names")
+    }
+
+    /// Creates the temporary file an entry is written to before it is moved to `path`.
+    /// It is created in the same directory, so that the move is a rename that cannot leave a
+    /// partial file behind, with the permissions `File::create` would give the final file.
+    pub fn temp_file_for(&self, path: &Path) -> io::Result<NamedTempFile> {
+        let dir = path.parent().unwrap_or(&self.dir);
+        fs::create_dir_all(dir)?;
+        let mut builder = tempfile::Builder::new();
+        builder.prefix(".extract-").suffix(".tmp");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            builder.permissions(fs::Permissions::from_mode(0o666));
+        }
+        builder.tempfile_in(dir)
+    }
+
+    /// Moves a completely written and closed temporary file to `path`, as chosen by
+    /// `resolve_conflict`. Only the overwrite policy may replace a file; otherwise a file that
+    /// has appeared at `path` in the meantime is an error.
+    pub fn persist(&self, file: TempPath, path: &Path) -> Result<(), Error> {
+        let result = if self.on_conflict == ConflictPolicy::Overwrite {
+            file.persist(path)
+        } else {
+            file.persist_noclobber(path)
+        };
+        match result {
+            Ok(()) => Ok(()),
+            Err(e) if e.error.kind() == io::ErrorKind::AlreadyExists => Err(already_exists(path)),
+            Err(e) => Err(Error::new(ErrorKind::OutputIo, format!("Cannot write {}: {}", path.display(), e.error))),
+        }
+    }
+
+    /// Applies the modification time and Unix mode recorded in the archive to the written file
+    /// at `path`, as far as the options ask for it and the archive records them. Setuid, setgid
+    /// and sticky bits are never applied.
+    pub fn apply_metadata(&self, path: &Path, modified: Option<EntryTime>, unix_mode: Option<u32>) -> io::Result<()> {
+        if let Some(modified) = modified.filter(|_| self.preserve_mtime) {
+            let file = File::options().write(true).open(path)?;
+            file.set_modified(modified.to_offset_date_time(self.dos_offset).into())?;
+        }
+        #[cfg(unix)]
+        if let Some(mode) = unix_mode.filter(|_| self.preserve_mode) {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777))?;
+        }
+        #[cfg(not(unix))]
+        let _ = unix_mode;
+        Ok(())
+    }
+
+    /// Makes `path` a hard link to `original`, replacing any existing file as `persist` would.
+    pub fn link(&self, original: &Path, path: &Path) -> Result<(), Error> {
+        let dir = path.parent().unwrap_or(&self.dir);
+        let link = tempfile::Builder::new()
+            .prefix(".extract-")
+            .suffix(".tmp")
+            .make_in(dir, |link| fs::hard_link(original, link))
+            .map_err(|e| {
+                let message = format!("Cannot link {} to {}: {}", path.display(), original.display(), e);
+                Error::new(ErrorKind::OutputIo, message)
+            })?;
+        self.persist(link.into_temp_path(), path)
+    }
+}
+
+fn already_exists(path: &Path) -> Error {
+    Error::new(ErrorKind::OutputExists, format!("Output file already exists: {}", path.display()))
+}
+
+/// Inserts `suffix` between the file stem and the extension, e.g. `a/b.txt` -> `a/b_1.txt`.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = OsString::new();
+    if let Some(stem) = path.file_stem() {
+        file_name.push(stem);
+    }
+    file_name.push(suffix);
+    if let Some(ext) = path.extension() {
+        file_name.push(".");
+        file_name.push(ext);
+    }
+    path.with_file_name(file_name)
+}
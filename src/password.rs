@@ -0,0 +1,59 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Candidate passwords for encrypted entries, tried in order.
+#[derive(Debug, Clone, Default)]
+pub struct Passwords {
+    candidates: Vec<Vec<u8>>,
+}
+
+impl Passwords {
+    /// Gathers candidates from `--password` values, then the lines of `--password-file`,
+    /// then the `--password-env` variable. Empty lines in the file are ignored.
+    pub fn load(passwords: &[String], file: Option<&Path>, env_var: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        let mut candidates: Vec<Vec<u8>> = passwords.iter().map(|p| p.as_bytes().to_vec()).collect();
+
+        if let Some(file) = file {
+            let contents = fs::read(file).map_err(|e| format!("Cannot read password file {}: {}", file.display(), e))?;
+            for line in contents.split(|&b| b == b'\n') {
+                let line = line.strip_suffix(b"\r").unwrap_or(line);
+                if !line.is_empty() {
+                    candidates.push(line.to_vec());
+                }
+            }
+        }
+
+        if let Some(name) = env_var {
+            let value = env::var_os(name).ok_or_else(|| format!("Environment variable {} is not set", name))?;
+            candidates.push(value.into_encoded_bytes());
+        }
+
+        Ok(Passwords { candidates })
+    }
+
+    /// Returns true if no passwords were given.
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    /// Returns the index of the first candidate accepted by `try_password`, starting with
+    /// `preferred` (typically the one that worked for the previous entry of the same archive).
+    pub fn find(&self, preferred: Option<usize>, mut try_password: impl FnMut(&[u8]) -> bool) -> Option<usize> {
+        preferred
+            .into_iter()
+            .chain((0..self.candidates.len()).filter(|&i| Some(i) != preferred))
+            .find(|&i| try_password(&self.candidates[i]))
+    }
+
+    /// Iterates over the candidates in order.
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        self.candidates.iter().map(Vec::as_slice)
+    }
+
+    /// Returns the candidate at `index`, as returned by `find`.
+    pub fn get(&self, index: usize) -> &[u8] {
+        &self.candidates[index]
+    }
+}
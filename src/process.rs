@@ -0,0 +1,1068 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Condvar, Mutex};
+use std::thread;
+
+use tempfile::TempPath;
+
+use crate::archive::{self, ArchiveReader, Compression, Entry, EntryMeta, EntryTime, Opened};
+use crate::dedupe::{Checked, DedupeMode, Deduplicator};
+use crate::error::{Error, ErrorKind};
+use crate::extractor::Mode;
+use crate::filter::EntryFilter;
+use crate::hash::{ContentHash, HashingWriter};
+use crate::integrity::CrcReader;
+use crate::limits::{LimitExceeded, Limits};
+use crate::output::{ConflictPolicy, OutputOptions};
+use crate::password::Passwords;
+use crate::report::{self, ManifestRecord, Outcome, Report, Reporter};
+use crate::{sanitize, sniff};
+
+/// Everything that decides which entries are extracted and how, shared by all archives in a run.
+pub struct ExtractOptions {
+    pub mode: Mode,
+    pub filter: EntryFilter,
+    pub output: OutputOptions,
+    /// Treat any unsafe entry name as an error for the whole archive.
+    pub strict: bool,
+    pub limits: Limits,
+    /// Candidate passwords for encrypted entries.
+    pub passwords: Passwords,
+    /// How many levels of nested archives to open, or `None` to treat them as plain files.
+    pub nested_depth: Option<usize>,
+    /// Recognises duplicate files, if deduplication is on.
+    pub dedupe: Option<Deduplicator>,
+    pub reporter: Reporter,
+}
+
+/// Nested archives up to this size are opened in memory; larger ones are spooled to a temporary file.
+const NESTED_IN_MEMORY_MAX: u64 = 64 * 1024 * 1024;
+
+/// Processes archives on `jobs` worker threads. Each archive's messages and finished files are
+/// held back and replayed in the order of `archives` as soon as all earlier ones are done, so the
+/// log and the output directory end up as they would after a sequential run. Workers stay
+/// within a `Window` of the next archive to replay, so that finished archives waiting their
+/// turn cannot pile up.
+pub fn process_in_parallel(archives: &[PathBuf], jobs: usize, options: &ExtractOptions) {
+    // More workers than archives would have nothing to do, and the window and channel are sized by them.
+    let jobs = jobs.min(archives.len());
+    let next = AtomicUsize::new(0);
+    let window = Window::new(2 * jobs);
+    let (sender, receiver) = mpsc::sync_channel(jobs);
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let sender = sender.clone();
+            let next = &next;
+            let window = &window;
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(path) = archives.get(index).filter(|_| window.wait_for(index)) else {
+                    break;
+                };
+                let journal = Journal::deferred(options);
+                let result = process_archive_file(path, options, &journal, 1);
+                if sender.send((path, journal.into_events(), result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        // Archives finish in any order; replay each once every archive before it has been.
+        let mut finished = HashMap::new();
+        let mut in_order = archives.iter();
+        let mut waiting_for = in_order.next();
+        let mut replayed = 0;
+        for (path, events, result) in receiver {
+            finished.insert(path, (events, result));
+            while let Some(path) = waiting_for {
+                let Some((events, result)) = finished.remove(path) else {
+                    break;
+                };
+                options.reporter.archive(path);
+                if let Err(e) = replay(events, options).and(result) {
+                    report_archive_error(path, e, options);
+                }
+                waiting_for = in_order.next();
+                replayed += 1;
+                window.advance(replayed);
+            }
+        }
+    });
+}
+
+/// Keeps the workers of `process_in_parallel` and `process_entries_in_parallel` from starting
+/// items too far ahead of the next one to be passed on in order. Each finished item holds its
+/// staged files until then, so without a limit a slow item would let them pile up.
+struct Window {
+    size: usize,
+    /// Index of the next item to be passed on, or `None` once no more results are wanted.
+    next_in_order: Mutex<Option<usize>>,
+    advanced: Condvar,
+}
+
+impl Window {
+    fn new(size: usize) -> Self {
+        Window {
+            size,
+            next_in_order: Mutex::new(Some(0)),
+            advanced: Condvar::new(),
+        }
+    }
+
+    /// Waits until item `index` is close enough to be started. Returns false if no more
+    /// results are wanted.
+    fn wait_for(&self, index: usize) -> bool {
+        let next_in_order = self.next_in_order.lock().unwrap();
+        let next_in_order = self
+            .advanced
+            .wait_while(next_in_order, |next| next.is_some_and(|next| index >= next + self.size))
+            .unwrap();
+        next_in_order.is_some()
+    }
+
+    /// Notes that the items before `next_in_order` have been passed on.
+    fn advance(&self, next_in_order: usize) {
+        *self.next_in_order.lock().unwrap() = Some(next_in_order);
+        self.advanced.notify_all();
+    }
+
+    /// Releases all waiting workers, telling them to stop.
+    fn close(&self) {
+        *self.next_in_order.lock().unwrap() = None;
+        self.advanced.notify_all();
+    }
+}
+
+/// Reports an archive that could not be processed, or was only partly processed.
+pub fn report_archive_error(path: &Path, error: Error, options: &ExtractOptions) {
+    options.reporter.archive_error(error.in_archive(path.display()));
+}
+
+/// Reports the duplicates found and a summary, and writes the manifest at the end of a run.
+pub fn finish(options: &ExtractOptions) -> Result<Report, Error> {
+    let duplicates = options.dedupe.as_ref().map(Deduplicator::groups).unwrap_or_default();
+    if !duplicates.is_empty() {
+        let count: usize = duplicates.iter().map(|(_, copies)| copies.len()).sum();
+        options.reporter.status(format_args!("Duplicates: {} files had the same contents as another:", count));
+        for (kept, copies) in &duplicates {
+            options.reporter.status(format_args!("  {}", kept.display()));
+            for copy in copies {
+                options.reporter.status(format_args!("    = {}", copy.display()));
+            }
+        }
+    }
+
+    let summary = options.reporter.summary();
+    let done = match options.mode {
+        Mode::Extract => "extracted",
+        Mode::List => "listed",
+        Mode::Verify => "verified",
+    };
+    let written = match options.mode {
+        Mode::Extract => format!("; {} bytes written", summary.bytes_written),
+        Mode::List | Mode::Verify => String::new(),
+    };
+    options.reporter.status(format_args!(
+        "Summary: {} archives scanned, {} failed; {} entries matched: {} {}, {} skipped, {} failed{}",
+        summary.archives,
+        summary.archives_failed,
+        summary.matched,
+        summary.extracted,
+        done,
+        summary.skipped,
+        summary.failed,
+        written
+    ));
+    options.reporter.finish(duplicates)
+}
+
+/// Processes a single archive file by extracting all files accepted by the given filter.
+/// The archive is read in a single pass regardless of how many criteria are given.
+/// Files whose names include "__MACOSX" are skipped.
+/// Entry names are sanitized before use; unsafe names are skipped with a warning, or abort the
+/// archive in strict mode.
+/// Entries that break a resource limit, either by their declared sizes or while being
+/// decompressed, are skipped, as are entries whose data is corrupt.
+/// Each file is written to a temporary file first and only moved into place once it is
+/// complete, so the output directory never contains partial files.
+/// Encrypted entries are decrypted with the first candidate password that works; those that
+/// cannot be decrypted are skipped and listed.
+/// Compressed entries such as "report.csv.gz" that do not match the filter themselves are
+/// decompressed, and the file inside is filtered and extracted under its own name.
+/// The extracted files are saved under the output directory at the path given by the layout,
+/// which by default is just the original file name.
+/// When an output file already exists, the conflict policy decides whether it is overwritten,
+/// kept, written under a new name, or reported as an error.
+/// Messages and finished files are passed to `journal`.
+/// With `jobs` above one, the entries of archives that can be read in any order are
+/// decompressed on that many threads.
+/// An error aborts the archive; it carries the archive and, where there is one, the entry
+/// it concerns.
+pub fn process_archive_file(
+    archive_path: &Path,
+    options: &ExtractOptions,
+    journal: &Journal,
+    jobs: usize,
+) -> Result<(), Error> {
+    let mut reader = open_archive_file(archive_path, options).map_err(|e| e.in_archive(archive_path.display()))?;
+    check_names_strictly(reader.as_mut(), options).map_err(|e| e.in_archive(archive_path.display()))?;
+    if jobs > 1 && reader.random_access() && options.limits.max_archive_size.is_none() {
+        if let Some(count) = reader.entry_count() {
+            options.limits.check_entry_count(count).map_err(|e| limit_error(e).in_archive(archive_path.display()))?;
+            return process_entries_in_parallel(archive_path, count, jobs, options, journal);
+        }
+    }
+    let source = ArchiveSource {
+        path: archive_path,
+        chain: archive_path.display().to_string(),
+        depth: 0,
+        journal,
+    };
+    process_archive(reader.as_mut(), &source, options)
+}
+
+/// Opens an archive file, detecting its format.
+fn open_archive_file<'a>(archive_path: &Path, options: &'a ExtractOptions) -> Result<Box<dyn ArchiveReader + 'a>, Error> {
+    let file = File::open(archive_path).map_err(|e| Error::new(ErrorKind::InputIo, e))?;
+    let name = archive_path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    Ok(archive::open(Box::new(file), name, &options.passwords)?)
+}
+
+/// Processes the `count` entries of one archive on `jobs` worker threads, each reading from its
+/// own handle on the file. As in `process_in_parallel`, each entry's messages and finished files
+/// are held back and passed to `journal` in archive order, with workers kept within a `Window`
+/// of the next entry to pass on, and the first entry that fails aborts the archive just as it
+/// would in a sequential run.
+fn process_entries_in_parallel(
+    archive_path: &Path,
+    count: usize,
+    jobs: usize,
+    options: &ExtractOptions,
+    journal: &Journal,
+) -> Result<(), Error> {
+    // More workers than entries would have nothing to do, and the window and channel are sized by them.
+    let jobs = jobs.min(count);
+    let next = AtomicUsize::new(0);
+    let window = Window::new(2 * jobs);
+    let (sender, receiver) = mpsc::sync_channel(jobs);
+    let mut undecryptable = 0;
+    let result = thread::scope(|scope| {
+        for _ in 0..jobs {
+            let sender = sender.clone();
+            let next = &next;
+            let window = &window;
+            scope.spawn(move || {
+                let mut reader = open_archive_file(archive_path, options);
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    if index >= count || !window.wait_for(index) {
+                        break;
+                    }
+                    let entry_journal = Journal::deferred(options);
+                    let source = ArchiveSource {
+                        path: archive_path,
+                        chain: archive_path.display().to_string(),
+                        depth: 0,
+                        journal: &entry_journal,
+                    };
+                    let mut state = ArchiveState::default();
+                    let result = match &mut reader {
+                        Ok(reader) => reader
+                            .visit_entry(index, &mut |entry| visit_entry(entry, &source, &mut state, options))
+                            .map_err(Error::from),
+                        Err(e) => Err(e.clone()),
+                    };
+                    if sender.send((index, entry_journal.into_events(), state.undecryptable, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        // Entries finish in any order; pass each on once every entry before it has been.
+        // On an error, closing the window and dropping the receiver stop the workers after
+        // their current entry.
+        let mut finished = HashMap::new();
+        let mut waiting_for = 0;
+        let result = receiver.into_iter().try_for_each(|(index, events, entry_undecryptable, result)| {
+            finished.insert(index, (events, entry_undecryptable, result));
+            while let Some((events, entry_undecryptable, result)) = finished.remove(&waiting_for) {
+                journal.extend(events)?;
+                result?;
+                undecryptable += entry_undecryptable;
+                waiting_for += 1;
+                window.advance(waiting_for);
+            }
+            Ok::<(), Error>(())
+        });
+        window.close();
+        result
+    });
+    result.map_err(|e| e.in_archive(archive_path.display()))?;
+
+    if undecryptable > 0 {
+        journal.warning(format_args!(
+            "Warning: {} encrypted entries in {} could not be decrypted.",
+            undecryptable,
+            archive_path.display()
+        ));
+    }
+    Ok(())
+}
+
+/// Identifies the archive being processed.
+struct ArchiveSource<'a> {
+    /// Names the archive for output layouts.
+    path: &'a Path,
+    /// Identifies the archive in logs, e.g. "outer.zip!/inner.zip".
+    chain: String,
+    /// Number of archives this one is nested in.
+    depth: usize,
+    /// Where messages and finished files from the archive go; shared with nested archives.
+    journal: &'a Journal<'a>,
+}
+
+impl ArchiveSource<'_> {
+    /// Entries of nested archives are logged with their full chain, e.g. "outer.zip!/inner.zip!/a.png".
+    fn describe(&self, entry_name: &str) -> String {
+        if self.depth == 0 {
+            entry_name.to_string()
+        } else {
+            format!("{}!/{}", self.chain, entry_name)
+        }
+    }
+
+    /// An error about one of the archive's entries.
+    fn entry_error(&self, kind: ErrorKind, entry_name: &str, message: impl Display) -> Error {
+        Error::new(kind, message).at_entry(entry_name).in_archive(&self.chain)
+    }
+}
+
+/// Something that happened while processing an archive, held back by a deferred `Journal`.
+enum Event {
+    Status(String),
+    Skipped(String),
+    Verified(String),
+    Warning(String),
+    Error(Error),
+    List(Box<ListedFile>),
+    Commit(Box<StagedFile>),
+}
+
+/// Where the messages and finished files from processing an archive go. An immediate journal
+/// reports and commits them straight away. A deferred one records them, so that archives
+/// processed in parallel can be replayed one after the other in a fixed order, keeping both the
+/// log and the handling of name collisions the same as in a sequential run.
+pub struct Journal<'a> {
+    options: &'a ExtractOptions,
+    deferred: Option<RefCell<Vec<Event>>>,
+}
+
+impl<'a> Journal<'a> {
+    pub fn immediate(options: &'a ExtractOptions) -> Self {
+        Journal { options, deferred: None }
+    }
+
+    fn deferred(options: &'a ExtractOptions) -> Self {
+        Journal {
+            options,
+            deferred: Some(RefCell::new(Vec::new())),
+        }
+    }
+
+    /// Returns what a deferred journal recorded, in order.
+    fn into_events(self) -> Vec<Event> {
+        self.deferred.map(RefCell::into_inner).unwrap_or_default()
+    }
+
+    /// Reports progress, as `Reporter::status` does.
+    fn status(&self, message: impl Display) {
+        match &self.deferred {
+            Some(events) => events.borrow_mut().push(Event::Status(message.to_string())),
+            None => self.options.reporter.status(message),
+        }
+    }
+
+    /// Reports a matching entry that is not written, as `Reporter::skipped` does.
+    fn skipped(&self, message: impl Display) {
+        match &self.deferred {
+            Some(events) => events.borrow_mut().push(Event::Skipped(message.to_string())),
+            None => self.options.reporter.skipped(message),
+        }
+    }
+
+    /// Reports an intact entry, as `Reporter::verified` does.
+    fn verified(&self, message: impl Display) {
+        match &self.deferred {
+            Some(events) => events.borrow_mut().push(Event::Verified(message.to_string())),
+            None => self.options.reporter.verified(message),
+        }
+    }
+
+    /// Reports a warning, as `Reporter::warning` does.
+    fn warning(&self, message: impl Display) {
+        match &self.deferred {
+            Some(events) => events.borrow_mut().push(Event::Warning(message.to_string())),
+            None => self.options.reporter.warning(message),
+        }
+    }
+
+    /// Reports a skipped entry, as `Reporter::error` does.
+    fn error(&self, error: Error) {
+        match &self.deferred {
+            Some(events) => events.borrow_mut().push(Event::Error(error)),
+            None => self.options.reporter.error(error),
+        }
+    }
+
+    /// Reports a file that is listed rather than written, with `list_file`.
+    fn list(&self, listed: ListedFile) -> Result<(), Error> {
+        match &self.deferred {
+            Some(events) => events.borrow_mut().push(Event::List(Box::new(listed))),
+            None => list_file(listed, self.options)?,
+        }
+        Ok(())
+    }
+
+    /// Passes on the events recorded by another, deferred journal, as if they had happened here.
+    fn extend(&self, events: Vec<Event>) -> Result<(), Error> {
+        match &self.deferred {
+            Some(deferred) => deferred.borrow_mut().extend(events),
+            None => replay(events, self.options)?,
+        }
+        Ok(())
+    }
+
+    /// Moves a staged file into place with `commit_file`.
+    fn commit(&self, staged: StagedFile) -> Result<(), Error> {
+        match &self.deferred {
+            Some(events) => events.borrow_mut().push(Event::Commit(Box::new(staged))),
+            None => commit_file(staged, self.options)?,
+        }
+        Ok(())
+    }
+}
+
+/// Carries out the events of a deferred journal, stopping at the first file that cannot be
+/// committed. The staged files of any remaining events are discarded.
+fn replay(events: Vec<Event>, options: &ExtractOptions) -> Result<(), Error> {
+    for event in events {
+        match event {
+            Event::Status(message) => options.reporter.status(message),
+            Event::Skipped(message) => options.reporter.skipped(message),
+            Event::Verified(message) => options.reporter.verified(message),
+            Event::Warning(message) => options.reporter.warning(message),
+            Event::Error(error) => options.reporter.error(error),
+            Event::List(listed) => list_file(*listed, options)?,
+            Event::Commit(staged) => commit_file(*staged, options)?,
+        }
+    }
+    Ok(())
+}
+
+/// Running totals for one archive.
+#[derive(Default)]
+struct ArchiveState {
+    /// Entries visited so far, for the entry-count limit of formats that cannot tell up front.
+    entries: usize,
+    /// Bytes extracted from this archive so far, for the per-archive limit.
+    total_bytes: u64,
+    /// Encrypted entries that could not be decrypted.
+    undecryptable: usize,
+}
+
+/// Extracts the matching entries of an open archive; see `process_archive_file`.
+fn process_archive(
+    reader: &mut dyn ArchiveReader,
+    source: &ArchiveSource,
+    options: &ExtractOptions,
+) -> Result<(), Error> {
+    if let Some(count) = reader.entry_count() {
+        options.limits.check_entry_count(count).map_err(|e| limit_error(e).in_archive(&source.chain))?;
+    }
+    if source.depth > 0 {
+        check_names_strictly(reader, options).map_err(|e| e.in_archive(&source.chain))?;
+    }
+
+    let mut state = ArchiveState::default();
+    reader
+        .for_each_entry(&mut |entry| {
+            state.entries += 1;
+            options.limits.check_entry_count(state.entries).map_err(limit_error)?;
+            visit_entry(entry, source, &mut state, options)
+        })
+        .map_err(|e| Error::from(e).in_archive(&source.chain))?;
+
+    if state.undecryptable > 0 {
+        source.journal.warning(format_args!(
+            "Warning: {} encrypted entries in {} could not be decrypted.",
+            state.undecryptable, source.chain
+        ));
+    }
+
+    Ok(())
+}
+
+/// In strict mode, checks the names of all entries of an archive that lists them up front, so
+/// that an unsafe name aborts the archive before anything is extracted from it. The names of
+/// formats read as a stream, such as tar, are only checked as each entry is reached.
+fn check_names_strictly(reader: &mut dyn ArchiveReader, options: &ExtractOptions) -> Result<(), Error> {
+    if !options.strict {
+        return Ok(());
+    }
+    for name in reader.file_names()?.unwrap_or_default() {
+        if !name.contains("__MACOSX") {
+            check_name_strictly(&name).map_err(|e| e.at_entry(&name))?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns the error that aborts an archive in strict mode if `name` is unsafe.
+fn check_name_strictly(name: &str) -> Result<(), Error> {
+    match sanitize::sanitize_entry_name(name) {
+        Ok(sanitized) if sanitized.rewrites.is_empty() => Ok(()),
+        Ok(sanitized) => {
+            let message = format!("Unsafe entry: {}", sanitized.rewrites.join(", "));
+            Err(Error::new(ErrorKind::UnsafePath, message))
+        }
+        Err(reason) => Err(Error::new(ErrorKind::UnsafePath, format!("Unsafe entry: name {}", reason))),
+    }
+}
+
+/// Calls `process_entry` on behalf of an `ArchiveReader`, attributing any error to the entry.
+fn visit_entry(
+    entry: &mut dyn Entry,
+    source: &ArchiveSource,
+    state: &mut ArchiveState,
+    options: &ExtractOptions,
+) -> Result<(), Box<dyn StdError>> {
+    let name = entry.meta().name.clone();
+    process_entry(entry, source, state, options).map_err(|e| e.at_entry(&name).into())
+}
+
+fn limit_error(error: LimitExceeded) -> Error {
+    Error::new(ErrorKind::LimitExceeded, error)
+}
+
+/// Extracts a single entry if it is accepted by the filter, or descends into it if it is a
+/// nested archive. Problems confined to the entry are reported and the entry skipped;
+/// an `Err` aborts the whole archive.
+fn process_entry(
+    entry: &mut dyn Entry,
+    source: &ArchiveSource,
+    state: &mut ArchiveState,
+    options: &ExtractOptions,
+) -> Result<(), Error> {
+    let filter = &options.filter;
+    let output = &options.output;
+    let meta = entry.meta().clone();
+    // Noteworthy things about the entry, recorded in the manifest if it is extracted.
+    let mut warnings = Vec::new();
+
+    // Skip any entry that is part of the "__MACOSX" metadata, and directories.
+    if meta.name.contains("__MACOSX") || !meta.is_file {
+        return Ok(());
+    }
+
+    // Make the name safe to use as a path before it is filtered or written anywhere.
+    if options.strict {
+        check_name_strictly(&meta.name)?;
+    }
+    let entry_name = match sanitize::sanitize_entry_name(&meta.name) {
+        Ok(sanitized) if sanitized.rewrites.is_empty() => sanitized.name,
+        Ok(sanitized) => {
+            let reason = sanitized.rewrites.join(", ");
+            source.journal.warning(format_args!(
+                "Warning: Renamed unsafe entry {:?} to {:?}: {}",
+                source.describe(&meta.name),
+                sanitized.name,
+                reason
+            ));
+            warnings.push(format!("Renamed unsafe entry {:?} to {:?}: {}", meta.name, sanitized.name, reason));
+            sanitized.name
+        }
+        // Unsafe entries are only failures if they would have been extracted.
+        Err(reason) if filter.matches_name(&meta.name) => {
+            let message = format!("Skipped unsafe entry: name {}", reason);
+            source.journal.error(source.entry_error(ErrorKind::UnsafePath, &meta.name, message));
+            return Ok(());
+        }
+        Err(reason) => {
+            source.journal.warning(format_args!(
+                "Warning: Skipped unsafe entry {:?}: name {}",
+                source.describe(&meta.name),
+                reason
+            ));
+            return Ok(());
+        }
+    };
+
+    // Nested archives are recognised by name, or by content when classifying by content.
+    let can_descend = options.nested_depth.is_some_and(|max| source.depth < max);
+    let named_archive = archive::is_archive_path(Path::new(&entry_name));
+    let maybe_nested = can_descend && (named_archive || filter.needs_content());
+
+    // Single compressed files are always unwrapped in a top-level archive, and in nested ones
+    // as far as --nested allows. Compressed tarballs are archives, not single files.
+    let can_unwrap = source.depth == 0 || can_descend;
+    let maybe_compressed = can_unwrap
+        && !named_archive
+        && (filter.needs_content() || Compression::from_name(&entry_name).is_some());
+
+    // Only process entries that pass the name filter, or may contain other files.
+    if !maybe_nested && !maybe_compressed && !filter.matches_name(&entry_name) {
+        return Ok(());
+    }
+
+    // Encrypted entries are decompressed to check each plausible password, so refuse those that
+    // admit to breaking a limit before that.
+    if meta.encrypted {
+        if let Err(e) = options.limits.check_declared(meta.size.unwrap_or(0), meta.compressed_size, state.total_bytes) {
+            let message = format!("Skipped entry: {}", e);
+            source.journal.error(source.entry_error(ErrorKind::LimitExceeded, &entry_name, message));
+            return Ok(());
+        }
+    }
+
+    // Open the entry's data, which for encrypted entries means finding a working password.
+    let compressed_count = entry.compressed_count();
+    let data = match entry.open()? {
+        Opened::Data(data) => data,
+        Opened::Undecryptable(reason) => {
+            let message = format!("Cannot decrypt entry: {}", reason);
+            source.journal.error(source.entry_error(ErrorKind::Encrypted, &entry_name, message));
+            state.undecryptable += 1;
+            return Ok(());
+        }
+    };
+    let data = CrcReader::new(data, meta.crc32);
+    let mut reader = match compressed_count {
+        Some(count) => options.limits.counted_reader(data, count, state.total_bytes),
+        None => options.limits.reader(data, meta.compressed_size, state.total_bytes),
+    };
+
+    // When classifying by content, read the leading bytes now; they are written out first below.
+    let mut head = Vec::new();
+    if filter.needs_content() || maybe_nested || maybe_compressed {
+        match (&mut reader).take(sniff::SNIFF_LEN as u64).read_to_end(&mut head) {
+            Ok(_) => {}
+            Err(e) if LimitExceeded::from_io(&e).is_some() => {
+                let message = format!("Aborted entry: {}", e);
+                source.journal.error(source.entry_error(ErrorKind::LimitExceeded, &entry_name, message));
+                return Ok(());
+            }
+            Err(e) if reader.get_ref().failed() => {
+                source.journal.error(source.entry_error(ErrorKind::Corrupt, &entry_name, format!("Corrupt entry: {}", e)));
+                return Ok(());
+            }
+            Err(e) => return Err(Error::reading(e)),
+        }
+    }
+    let kind = sniff::detect(&head);
+
+    // Descend into nested archives instead of extracting them. Compressed tarballs such as
+    // "pkg.tar.gz" only show their compression, so their names are trusted for what is inside.
+    let archive_kind = matches!(kind, Some(sniff::ZIP) | Some(sniff::SEVEN_ZIP) | Some(sniff::TAR));
+    let compressed_kind = kind.and_then(Compression::from_kind).is_some();
+    if maybe_nested && (archive_kind || (named_archive && (!filter.needs_content() || compressed_kind))) {
+        let nested = ArchiveSource {
+            path: Path::new(&entry_name),
+            chain: format!("{}!/{}", source.chain, entry_name),
+            depth: source.depth + 1,
+            journal: source.journal,
+        };
+        let result = options
+            .limits
+            .check_declared(meta.size.unwrap_or(0), meta.compressed_size, state.total_bytes)
+            .map_err(limit_error)
+            .and_then(|()| {
+                source.journal.status(format_args!("Processing nested archive: {}", nested.chain));
+                process_nested(head, &mut reader, &nested, options)
+            });
+        state.total_bytes += reader.bytes_read();
+        if let Err(e) = result {
+            report_entry_error(source.journal, &nested.chain, e, reader.get_ref().failed());
+        }
+        return Ok(());
+    }
+
+    let matches =
+        filter.matches_name(&entry_name) && (!filter.needs_content() || filter.matches_content(&entry_name, kind));
+
+    // Decompress single compressed files that are not wanted as they are, and filter their contents.
+    let compression = if filter.needs_content() {
+        kind.and_then(Compression::from_kind)
+    } else {
+        Compression::from_name(&entry_name)
+    };
+    if let Some(compression) = compression.filter(|_| maybe_compressed && !matches) {
+        let inner = ArchiveSource {
+            path: Path::new(&entry_name),
+            chain: format!("{}!/{}", source.chain, entry_name),
+            depth: source.depth + 1,
+            journal: source.journal,
+        };
+        let result = archive::open_compressed(
+            Box::new(head.as_slice().chain(&mut reader)),
+            compression,
+            &entry_name,
+            &head,
+            meta.compressed_size,
+        )
+        .map_err(Error::from)
+        .and_then(|mut compressed| process_archive(compressed.as_mut(), &inner, options));
+        state.total_bytes += reader.bytes_read();
+        if let Err(e) = result {
+            report_entry_error(source.journal, &inner.chain, e, reader.get_ref().failed());
+        }
+        return Ok(());
+    }
+
+    if !matches {
+        return Ok(());
+    }
+
+    // Refuse entries whose metadata already admits to breaking a limit.
+    if let Err(e) = options.limits.check_declared(meta.size.unwrap_or(0), meta.compressed_size, state.total_bytes) {
+        source.journal.error(source.entry_error(ErrorKind::LimitExceeded, &entry_name, format!("Skipped entry: {}", e)));
+        return Ok(());
+    }
+
+    // When verifying, read the rest of the entry to check it, and go no further.
+    if options.mode == Mode::Verify {
+        match io::copy(&mut reader, &mut io::sink()) {
+            Ok(_) => {
+                state.total_bytes += reader.bytes_read();
+                let checked = meta.crc32.map_or("no checksum".to_string(), |crc| format!("crc {:08x}", crc));
+                source.journal.verified(format_args!("OK: {} ({})", source.describe(&entry_name), checked));
+            }
+            Err(e) if LimitExceeded::from_io(&e).is_some() => {
+                let message = format!("Aborted entry: {}", e);
+                source.journal.error(source.entry_error(ErrorKind::LimitExceeded, &entry_name, message));
+            }
+            Err(e) if reader.get_ref().failed() => {
+                source.journal.error(source.entry_error(ErrorKind::Corrupt, &entry_name, format!("Corrupt entry: {}", e)));
+            }
+            Err(e) => return Err(Error::reading(e)),
+        }
+        return Ok(());
+    }
+
+    // Work out where the entry goes; entries without a usable path are skipped.
+    let relative_path = match output.relative_path(source.path, &entry_name, meta.modified) {
+        Ok(relative_path) => relative_path,
+        Err(message) => {
+            source.journal.warning(format_args!("Warning: {}", message));
+            return Ok(());
+        }
+    };
+    let target_path = output.dir.join(relative_path);
+
+    // Entries whose output would be kept anyway are not worth reading.
+    if output.on_conflict == ConflictPolicy::Skip && target_path.exists() {
+        source.journal.skipped(format_args!("Skipped (already exists): {}", target_path.display()));
+        return Ok(());
+    }
+
+    // Hash suffixes come from the checksum of the data, which tar archives and single compressed
+    // files do not record, so listing reads their data to name the files as extracting would.
+    let mut meta = meta;
+    if options.mode == Mode::List && output.on_conflict == ConflictPolicy::HashSuffix && meta.crc32.is_none() {
+        match io::copy(&mut reader, &mut io::sink()) {
+            Ok(_) => {
+                state.total_bytes += reader.bytes_read();
+                meta.crc32 = Some(reader.get_ref().crc32());
+            }
+            Err(e) if LimitExceeded::from_io(&e).is_some() => {
+                let message = format!("Aborted entry: {}", e);
+                source.journal.error(source.entry_error(ErrorKind::LimitExceeded, &entry_name, message));
+                return Ok(());
+            }
+            Err(e) if reader.get_ref().failed() => {
+                source.journal.error(source.entry_error(ErrorKind::Corrupt, &entry_name, format!("Corrupt entry: {}", e)));
+                return Ok(());
+            }
+            Err(e) => return Err(Error::reading(e)),
+        }
+    }
+
+    let record = ManifestRecord {
+        archive: source.chain.clone(),
+        entry: entry_name.clone(),
+        output: target_path.clone(),
+        size: meta.size,
+        compressed_size: meta.compressed_size,
+        crc32: meta.crc32.map(|crc| format!("{:08x}", crc)),
+        sha256: None,
+        modified: meta.modified.map(report::format_entry_time),
+        extracted_at: None,
+        duplicate_of: None,
+        warnings,
+    };
+
+    if options.mode == Mode::List {
+        return source.journal.list(ListedFile {
+            target_path,
+            entry: source.describe(&entry_name),
+            meta,
+            record,
+        });
+    }
+
+    // Write the data to a temporary file next to its destination, hashing it on the way, so that
+    // nothing is left half-written if the data breaks a limit, turns out to be corrupt, or the
+    // process is killed. The temporary file is removed when dropped.
+    let temp_file = output.temp_file_for(&target_path).map_err(|e| output_error(&target_path, e))?;
+    let mut outfile = HashingWriter::new(temp_file);
+    let written = match io::copy(&mut head.as_slice().chain(&mut reader), &mut outfile) {
+        Ok(written) => written,
+        Err(e) if LimitExceeded::from_io(&e).is_some() => {
+            let message = format!("Aborted entry: {}", e);
+            source.journal.error(source.entry_error(ErrorKind::LimitExceeded, &entry_name, message));
+            return Ok(());
+        }
+        Err(e) if reader.get_ref().failed() => {
+            source.journal.error(source.entry_error(ErrorKind::Corrupt, &entry_name, format!("Corrupt entry: {}", e)));
+            return Ok(());
+        }
+        Err(e) => return Err(output_error(&target_path, e)),
+    };
+    state.total_bytes += reader.bytes_read();
+    let (temp_file, hash) = outfile.finish();
+
+    // Close the file while it waits to be committed, which may take a while when processing in
+    // parallel; it is still removed if it is never moved into place.
+    source.journal.commit(StagedFile {
+        temp_file: temp_file.into_temp_path(),
+        target_path,
+        written,
+        crc32: reader.get_ref().crc32(),
+        hash,
+        modified: meta.modified,
+        unix_mode: meta.unix_mode,
+        nested_entry: (source.depth > 0).then(|| source.describe(&entry_name)),
+        record,
+    })
+}
+
+/// A completely written and verified file in a temporary location, waiting to be moved into place.
+struct StagedFile {
+    temp_file: TempPath,
+    /// Where the layout puts the file, before the conflict policy is applied.
+    target_path: PathBuf,
+    written: u64,
+    crc32: u32,
+    hash: ContentHash,
+    modified: Option<EntryTime>,
+    unix_mode: Option<u32>,
+    /// The entry's full chain, for files from nested archives.
+    nested_entry: Option<String>,
+    record: ManifestRecord,
+}
+
+/// Moves a staged file to its final name, as decided by the conflict policy and deduplication,
+/// and reports it. Its checksum is known at this point even for formats that do not record one.
+fn commit_file(staged: StagedFile, options: &ExtractOptions) -> Result<(), Error> {
+    let output = &options.output;
+    let StagedFile { temp_file, target_path, mut record, .. } = staged;
+    let Some(output_file_path) = output.resolve_conflict(target_path.clone(), Some(staged.crc32))? else {
+        options.reporter.skipped(format_args!("Skipped (already exists): {}", target_path.display()));
+        return Ok(());
+    };
+    let mut text = match &staged.nested_entry {
+        None => format!("Extracted: {}", output_file_path.display()),
+        Some(entry) => format!("Extracted: {} (from {})", output_file_path.display(), entry),
+    };
+
+    // Drop or link the file instead if the same contents were already extracted.
+    let checked = options.dedupe.as_ref().map(|dedupe| (dedupe.mode, dedupe.check(staged.hash, &output_file_path)));
+    if let Some((_, Checked { move_to: Some(move_to), .. })) = &checked {
+        move_original(&output_file_path, move_to, options)?;
+    }
+    let duplicate = checked.and_then(|(mode, checked)| checked.original.map(|original| (mode, original)));
+    let outcome = match duplicate {
+        Some((DedupeMode::Skip, original)) => {
+            text = format!(
+                "Duplicate: {} (from {}) has the same contents as {}, not written",
+                output_file_path.display(),
+                staged.nested_entry.as_deref().unwrap_or(&record.entry),
+                original.display()
+            );
+            record.duplicate_of = Some(original);
+            Outcome::Skipped
+        }
+        Some((DedupeMode::Hardlink, original)) => {
+            output.link(&original, &output_file_path)?;
+            text = format!("Linked: {} -> {}", output_file_path.display(), original.display());
+            record.duplicate_of = Some(original);
+            Outcome::Extracted(0)
+        }
+        None => {
+            output
+                .apply_metadata(&temp_file, staged.modified, staged.unix_mode)
+                .map_err(|e| output_error(&output_file_path, e))?;
+            output.persist(temp_file, &output_file_path)?;
+            Outcome::Extracted(staged.written)
+        }
+    };
+
+    record.size = Some(staged.written);
+    record.crc32 = Some(format!("{:08x}", staged.crc32));
+    record.sha256 = Some(staged.hash.to_string());
+    record.extracted_at = Some(report::now());
+    note_renamed(&mut record, &target_path, output_file_path);
+    options.reporter.record(record, text, outcome)
+}
+
+/// Moves the file at `path`, the only copy of the duplicates that were left out, to `move_to`,
+/// the output path of one of them, so that `path` can be overwritten.
+fn move_original(path: &Path, move_to: &Path, options: &ExtractOptions) -> Result<(), Error> {
+    if let Some(dir) = move_to.parent() {
+        fs::create_dir_all(dir).map_err(|e| output_error(move_to, e))?;
+    }
+    fs::rename(path, move_to).map_err(|e| output_error(move_to, e))?;
+    options.reporter.moved_original(path, move_to);
+    Ok(())
+}
+
+/// An entry that would be extracted, waiting for its output path when listing.
+struct ListedFile {
+    /// Where the layout puts the file, before the conflict policy is applied.
+    target_path: PathBuf,
+    /// The entry as shown in the listing.
+    entry: String,
+    meta: EntryMeta,
+    record: ManifestRecord,
+}
+
+/// Reports a listed file with the output path that extracting it would get from the conflict
+/// policy, as `commit_file` does, counting the files listed before it as written.
+fn list_file(listed: ListedFile, options: &ExtractOptions) -> Result<(), Error> {
+    let output = &options.output;
+    let ListedFile { target_path, entry, meta, mut record } = listed;
+    let Some(output_file_path) = output.resolve_conflict(target_path.clone(), meta.crc32)? else {
+        options.reporter.skipped(format_args!("Skipped (already exists): {}", target_path.display()));
+        return Ok(());
+    };
+    output.claim(output_file_path.clone());
+    note_renamed(&mut record, &target_path, output_file_path);
+    let line = listing_line(&entry, &meta, &record.output);
+    options.reporter.record(record, line, Outcome::Extracted(0))
+}
+
+/// Sets the record's output path to where the conflict policy put the file, noting it in the
+/// warnings if that is not where the layout wanted it.
+fn note_renamed(record: &mut ManifestRecord, target_path: &Path, output_file_path: PathBuf) {
+    if output_file_path != target_path {
+        record.warnings.push(format!(
+            "Written as {} because {} already exists",
+            output_file_path.display(),
+            target_path.display()
+        ));
+    }
+    record.output = output_file_path;
+}
+
+/// Reports an error from processing the nested archive or compressed entry `chain`, reporting
+/// the entry as corrupt instead if its own data was bad.
+fn report_entry_error(journal: &Journal, chain: &str, error: Error, corrupt: bool) {
+    let error = if corrupt {
+        Error::new(ErrorKind::Corrupt, format!("Corrupt entry: {}", error.message()))
+    } else {
+        error
+    };
+    journal.error(error.in_archive(chain));
+}
+
+/// An error writing the output file at `path`.
+fn output_error(path: &Path, error: io::Error) -> Error {
+    Error::new(ErrorKind::OutputIo, format!("Cannot write {}: {}", path.display(), error))
+}
+
+/// Formats the `list` line for an entry that would be extracted to `path`: uncompressed and
+/// compressed sizes, modification time, CRC-32, then the entry and its destination.
+/// Values the archive does not record are shown as "-".
+fn listing_line(entry: &str, meta: &EntryMeta, path: &Path) -> String {
+    format!(
+        "{:>12} {:>12}  {:<23} {:>8}  {} -> {}",
+        meta.size.map_or("-".to_string(), |size| size.to_string()),
+        meta.compressed_size,
+        meta.modified.map_or("-".to_string(), |modified| modified.to_string()),
+        meta.crc32.map_or("-".to_string(), |crc| format!("{:08x}", crc)),
+        entry,
+        path.display()
+    )
+}
+
+/// Opens an archive stored as an entry of another archive and processes it like a top-level one.
+/// `head` holds the bytes already read from `reader`. Small archives are read into memory;
+/// larger ones are spooled to an anonymous temporary file, since most formats need to seek.
+/// Which it is depends on the data actually read, since the declared size may be a lie.
+fn process_nested(
+    head: Vec<u8>,
+    reader: &mut impl Read,
+    source: &ArchiveSource,
+    options: &ExtractOptions,
+) -> Result<(), Error> {
+    let mut data = head;
+    let limit = (NESTED_IN_MEMORY_MAX + 1).saturating_sub(data.len() as u64);
+    reader.take(limit).read_to_end(&mut data).map_err(Error::reading)?;
+    let input: Box<dyn archive::ReadSeek> = if data.len() as u64 <= NESTED_IN_MEMORY_MAX {
+        Box::new(Cursor::new(data))
+    } else {
+        let mut spool = tempfile::tempfile().map_err(Error::reading)?;
+        spool.write_all(&data).map_err(Error::reading)?;
+        drop(data);
+        io::copy(reader, &mut spool).map_err(Error::reading)?;
+        spool.rewind().map_err(Error::reading)?;
+        Box::new(spool)
+    };
+    let name = source.path.to_str().unwrap_or_default();
+    let mut nested = archive::open(input, name, &options.passwords).map_err(|e| Error::from(e).in_archive(&source.chain))?;
+    process_archive(nested.as_mut(), source, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn window_holds_workers_back_until_it_advances() {
+        let window = Window::new(2);
+        assert!(window.wait_for(0) && window.wait_for(1));
+        let started = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                assert!(window.wait_for(2));
+                started.store(1, Ordering::SeqCst);
+            });
+            thread::sleep(Duration::from_millis(50));
+            assert_eq!(started.load(Ordering::SeqCst), 0);
+            window.advance(1);
+        });
+        assert_eq!(started.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn closing_the_window_releases_waiting_workers() {
+        let window = Window::new(1);
+        thread::scope(|scope| {
+            let waiting = scope.spawn(|| window.wait_for(5));
+            thread::sleep(Duration::from_millis(50));
+            window.close();
+            assert!(!waiting.join().unwrap());
+        });
+        assert!(!window.wait_for(0));
+    }
+}
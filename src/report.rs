@@ -0,0 +1,290 @@
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use clap::ValueEnum;
+use serde::Serialize;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+use crate::archive::EntryTime;
+use crate::error::{Error, ErrorKind};
+
+/// How results are reported on standard output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ReportFormat {
+    /// Human-readable lines such as "Extracted: out/a.png".
+    #[default]
+    Text,
+    /// One JSON object per extracted file, one per line. Progress messages go to standard error.
+    Ndjson,
+}
+
+/// What is known about one extracted (or, when listing, matching) file.
+#[derive(Debug, Clone, Serialize)]
+pub struct ManifestRecord {
+    /// The archive the entry came from, e.g. "batch.zip" or "batch.zip!/inner.zip" when nested.
+    pub archive: String,
+    /// The sanitized entry name inside that archive.
+    pub entry: String,
+    /// Where the file was (or would be) written.
+    pub output: PathBuf,
+    /// Uncompressed size in bytes; when extracting, the number of bytes actually written.
+    pub size: Option<u64>,
+    /// Compressed size declared by the archive.
+    pub compressed_size: u64,
+    /// CRC-32 as 8 hex digits: computed from the data when extracting, as recorded in the
+    /// archive when listing.
+    pub crc32: Option<String>,
+    /// SHA-256 of the written contents, as 64 hex digits. Absent when listing.
+    pub sha256: Option<String>,
+    /// Modification time recorded in the archive. Times without a time zone (from zip files)
+    /// have no offset.
+    pub modified: Option<String>,
+    /// When the file was written, in UTC. Absent when listing.
+    pub extracted_at: Option<String>,
+    /// With `--dedupe`, the earlier file this one duplicates. The file at `output` is then
+    /// either absent or a hard link to it.
+    pub duplicate_of: Option<PathBuf>,
+    /// Anything noteworthy about the entry, such as a renamed unsafe name.
+    pub warnings: Vec<String>,
+}
+
+/// The outcome of a run, returned by `Extractor::run`.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// Every file extracted, or when listing every file that would be, in the order they were
+    /// reported. Duplicates found by deduplication are included, with `duplicate_of` set.
+    pub files: Vec<ManifestRecord>,
+    /// Each group of files with the same contents found by deduplication, as the path that was
+    /// kept and the paths of its duplicates.
+    pub duplicates: Vec<(PathBuf, Vec<PathBuf>)>,
+    /// Things worth knowing that did not stop a file from being extracted, such as a renamed
+    /// unsafe entry, as they would be printed on standard error.
+    pub warnings: Vec<String>,
+    /// Entries that were skipped and archives that could not be processed, in the order they
+    /// happened, each with what went wrong and where.
+    pub errors: Vec<Error>,
+    /// Counts of what happened.
+    pub summary: Summary,
+}
+
+/// Counts of what happened in a run, as printed at its end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Summary {
+    /// Archives processed, not counting nested ones.
+    pub archives: usize,
+    /// Archives that could not be processed, or were aborted part of the way through.
+    pub archives_failed: usize,
+    /// Entries that matched the filters: those extracted, skipped or failed. Entries that failed
+    /// before their content could be checked, such as undecryptable ones, count if their names
+    /// match.
+    pub matched: usize,
+    /// Files extracted, or when listing or verifying, listed or verified.
+    pub extracted: usize,
+    /// Matching entries deliberately not written: ones whose output already exists with the skip
+    /// policy, and duplicates with deduplication.
+    pub skipped: usize,
+    /// Entries that could not be extracted, e.g. because they are corrupt or encrypted.
+    pub failed: usize,
+    /// Bytes written to the output directory.
+    pub bytes_written: u64,
+}
+
+/// What became of a reported file, for the summary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Written with this many bytes, or linked to an earlier file with none, or listed.
+    Extracted(u64),
+    /// Not written, as it duplicates an earlier file.
+    Skipped,
+}
+
+/// Reports progress and results, and collects them for the `Report` and the manifest.
+/// Shared by everything in a run, so it can be used from several threads.
+pub struct Reporter {
+    /// How progress and results are printed, or `None` to print nothing.
+    echo: Option<ReportFormat>,
+    manifest: Option<PathBuf>,
+    records: Mutex<Vec<ManifestRecord>>,
+    warnings: Mutex<Vec<String>>,
+    errors: Mutex<Vec<Error>>,
+    summary: Mutex<Summary>,
+}
+
+impl Reporter {
+    /// `manifest` is the file the JSON manifest is written to by `finish`, if any.
+    pub fn new(echo: Option<ReportFormat>, manifest: Option<PathBuf>) -> Self {
+        Reporter {
+            echo,
+            manifest,
+            records: Mutex::new(Vec::new()),
+            warnings: Mutex::new(Vec::new()),
+            errors: Mutex::new(Vec::new()),
+            summary: Mutex::new(Summary::default()),
+        }
+    }
+
+    /// Prints a progress message, keeping standard output for records in NDJSON mode.
+    pub fn status(&self, message: impl Display) {
+        match self.echo {
+            Some(ReportFormat::Text) => println!("{}", message),
+            Some(ReportFormat::Ndjson) => eprintln!("{}", message),
+            None => {}
+        }
+    }
+
+    /// Reports the start of a top-level archive.
+    pub fn archive(&self, path: &Path) {
+        self.status(format_args!("Processing archive: {}", path.display()));
+        self.summary.lock().unwrap().archives += 1;
+    }
+
+    /// Reports a matching entry that is deliberately not written.
+    pub fn skipped(&self, message: impl Display) {
+        self.status(message);
+        self.summary.lock().unwrap().skipped += 1;
+    }
+
+    /// Reports an entry that was read in full and found intact.
+    pub fn verified(&self, message: impl Display) {
+        self.status(message);
+        self.summary.lock().unwrap().extracted += 1;
+    }
+
+    /// Reports a file: as `text` in text mode, or as a JSON line in NDJSON mode.
+    pub fn record(&self, record: ManifestRecord, text: impl Display, outcome: Outcome) -> Result<(), Error> {
+        match self.echo {
+            Some(ReportFormat::Text) => println!("{}", text),
+            Some(ReportFormat::Ndjson) => {
+                let line = serde_json::to_string(&record).map_err(|e| Error::new(ErrorKind::OutputIo, e))?;
+                println!("{}", line);
+            }
+            None => {}
+        }
+        self.records.lock().unwrap().push(record);
+        let mut summary = self.summary.lock().unwrap();
+        match outcome {
+            Outcome::Extracted(bytes_written) => {
+                summary.extracted += 1;
+                summary.bytes_written += bytes_written;
+            }
+            Outcome::Skipped => summary.skipped += 1,
+        }
+        Ok(())
+    }
+
+    /// Reports that the file at `from` was moved to `to`, the output path of one of its duplicates
+    /// that was not written, so that `from` could be overwritten. That duplicate now counts as
+    /// extracted, and the others as duplicates of it.
+    pub fn moved_original(&self, from: &Path, to: &Path) {
+        self.status(format_args!(
+            "Moved: {} -> {}, which has the same contents, before overwriting it",
+            from.display(),
+            to.display()
+        ));
+        let mut moved = false;
+        for record in self.records.lock().unwrap().iter_mut() {
+            if record.duplicate_of.as_deref() != Some(from) {
+                continue;
+            }
+            if record.output == to && !moved {
+                record.duplicate_of = None;
+                moved = true;
+            } else {
+                record.duplicate_of = Some(to.to_path_buf());
+            }
+        }
+        if moved {
+            let mut summary = self.summary.lock().unwrap();
+            summary.skipped -= 1;
+            summary.extracted += 1;
+        }
+    }
+
+    /// Reports a warning on standard error.
+    pub fn warning(&self, message: impl Display) {
+        let message = message.to_string();
+        if self.echo.is_some() {
+            eprintln!("{}", message);
+        }
+        self.warnings.lock().unwrap().push(message);
+    }
+
+    /// Reports an entry that could not be extracted on standard error, with its error code.
+    pub fn error(&self, error: Error) {
+        self.summary.lock().unwrap().failed += 1;
+        self.print_error(error);
+    }
+
+    /// Reports an archive that could not be processed, or was aborted, like `error`.
+    pub fn archive_error(&self, error: Error) {
+        self.summary.lock().unwrap().archives_failed += 1;
+        self.print_error(error);
+    }
+
+    fn print_error(&self, error: Error) {
+        if self.echo.is_some() {
+            eprintln!("Error [{}] {}", error.code(), error);
+        }
+        self.errors.lock().unwrap().push(error);
+    }
+
+    /// The counts so far, with `matched` filled in.
+    pub fn summary(&self) -> Summary {
+        let summary = *self.summary.lock().unwrap();
+        Summary {
+            matched: summary.extracted + summary.skipped + summary.failed,
+            ..summary
+        }
+    }
+
+    /// Writes the manifest file, if one was asked for, as a JSON array of records, and returns
+    /// everything reported. `duplicates` are the groups found by deduplication.
+    pub fn finish(&self, duplicates: Vec<(PathBuf, Vec<PathBuf>)>) -> Result<Report, Error> {
+        let report = Report {
+            summary: self.summary(),
+            files: mem::take(&mut *self.records.lock().unwrap()),
+            duplicates,
+            warnings: mem::take(&mut *self.warnings.lock().unwrap()),
+            errors: mem::take(&mut *self.errors.lock().unwrap()),
+        };
+        if let Some(path) = &self.manifest {
+            write_manifest(path, &report.files)
+                .map_err(|e| Error::new(ErrorKind::OutputIo, format!("Cannot write manifest {}: {}", path.display(), e)))?;
+        }
+        Ok(report)
+    }
+}
+
+/// Writes `records` to `path` as a pretty-printed JSON array.
+fn write_manifest(path: &Path, records: &[ManifestRecord]) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, records)?;
+    writeln!(writer)?;
+    writer.flush()
+}
+
+/// Formats an entry's modification time as RFC 3339, without an offset for zone-less DOS times.
+pub fn format_entry_time(time: EntryTime) -> String {
+    match time {
+        EntryTime::Dos(time) => format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            time.year(),
+            u8::from(time.month()),
+            time.day(),
+            time.hour(),
+            time.minute(),
+            time.second()
+        ),
+        EntryTime::Utc(time) => time.format(&Rfc3339).unwrap_or_default(),
+    }
+}
+
+/// The current time in RFC 3339 format, in UTC.
+pub fn now() -> String {
+    OffsetDateTime::now_utc().format(&Rfc3339).unwrap_or_default()
+}
@@ -0,0 +1,160 @@
+/// Device names Windows reserves regardless of extension (e.g. "CON.txt" is still the console).
+const WINDOWS_RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Characters that are not allowed in file names on Windows.
+const WINDOWS_INVALID: &[char] = &['<', '>', ':', '"', '|', '?', '*'];
+
+/// An entry name that is safe to use as a relative path on any platform.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SanitizedName {
+    /// The safe name, with `/` separators and no empty, `.` or `..` components.
+    pub name: String,
+    /// A description of every change made to the raw name. Empty if it was already safe.
+    pub rewrites: Vec<String>,
+}
+
+/// Turns a raw entry name into one that stays inside the output directory on every platform.
+///
+/// Like `ZipFile::enclosed_name`, names containing NUL, absolute names (including Windows drive
+/// letters and UNC paths) and names whose `..` components climb above the root are rejected,
+/// while `a/../b` is accepted as `b`. In addition, backslashes are treated as separators,
+/// Windows-reserved device names get a leading underscore, characters Windows does not allow
+/// are replaced with `_`, and trailing dots and spaces are removed.
+/// On `Err`, the name must not be used and the message explains why.
+pub fn sanitize_entry_name(raw: &str) -> Result<SanitizedName, String> {
+    let mut rewrites = Vec::new();
+
+    if raw.contains('\0') {
+        return Err("contains a NUL byte".to_string());
+    }
+
+    let mut name = raw.to_string();
+    if name.contains('\\') {
+        name = name.replace('\\', "/");
+        rewrites.push("backslashes treated as directory separators".to_string());
+    }
+    if name.starts_with('/') {
+        return Err("is an absolute path".to_string());
+    }
+    let bytes = name.as_bytes();
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        return Err("starts with a drive letter".to_string());
+    }
+
+    let mut components: Vec<String> = Vec::new();
+    for component in name.split('/') {
+        match component {
+            "" | "." => continue,
+            ".." => {
+                if components.pop().is_none() {
+                    return Err("escapes the output directory with '..'".to_string());
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        let mut cleaned: String = component
+            .chars()
+            .map(|c| if c.is_control() || WINDOWS_INVALID.contains(&c) { '_' } else { c })
+            .collect();
+        if cleaned != component {
+            rewrites.push(format!("invalid characters in {:?} replaced", component));
+        }
+
+        let trimmed_len = cleaned.trim_end_matches(['.', ' ']).len();
+        if trimmed_len != cleaned.len() {
+            cleaned.truncate(trimmed_len);
+            rewrites.push(format!("trailing dots or spaces removed from {:?}", component));
+        }
+        if cleaned.is_empty() {
+            cleaned.push('_');
+        }
+
+        let device = cleaned.split('.').next().unwrap_or_default().trim_end();
+        if WINDOWS_RESERVED.iter().any(|reserved| reserved.eq_ignore_ascii_case(device)) {
+            cleaned.insert(0, '_');
+            rewrites.push(format!("reserved device name {:?} prefixed with '_'", component));
+        }
+
+        components.push(cleaned);
+    }
+
+    if components.is_empty() {
+        return Err("has no path components".to_string());
+    }
+    Ok(SanitizedName { name: components.join("/"), rewrites })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sanitized(raw: &str) -> String {
+        sanitize_entry_name(raw).unwrap().name
+    }
+
+    #[test]
+    fn dot_dot_may_not_climb_above_the_root() {
+        assert!(sanitize_entry_name("../evil.txt").is_err());
+        assert!(sanitize_entry_name("a/../../evil.txt").is_err());
+        assert!(sanitize_entry_name("a\\..\\..\\evil.txt").is_err());
+        assert_eq!(sanitized("a/../b.txt"), "b.txt");
+        assert_eq!(sanitized("./a/./b.txt"), "a/b.txt");
+    }
+
+    #[test]
+    fn absolute_names_are_rejected() {
+        assert!(sanitize_entry_name("/etc/passwd").is_err());
+        assert!(sanitize_entry_name("C:/Windows/evil.dll").is_err());
+        assert!(sanitize_entry_name("c:evil.txt").is_err());
+        assert!(sanitize_entry_name("C:\\Windows\\evil.dll").is_err());
+        assert!(sanitize_entry_name("\\\\server\\share\\evil.txt").is_err());
+        assert!(sanitize_entry_name("\\evil.txt").is_err());
+    }
+
+    #[test]
+    fn backslashes_are_separators() {
+        let name = sanitize_entry_name("dir\\sub\\file.txt").unwrap();
+        assert_eq!(name.name, "dir/sub/file.txt");
+        assert_eq!(name.rewrites.len(), 1);
+    }
+
+    #[test]
+    fn nul_bytes_are_rejected() {
+        assert!(sanitize_entry_name("evil.txt\0.png").is_err());
+    }
+
+    #[test]
+    fn reserved_device_names_are_prefixed() {
+        assert_eq!(sanitized("CON.txt"), "_CON.txt");
+        assert_eq!(sanitized("dir/nul"), "dir/_nul");
+        assert_eq!(sanitized("com1 .tar.gz"), "_com1 .tar.gz");
+        assert_eq!(sanitized("CONSOLE.txt"), "CONSOLE.txt");
+    }
+
+    #[test]
+    fn trailing_dots_and_spaces_are_removed() {
+        assert_eq!(sanitized("file.txt. "), "file.txt");
+        assert_eq!(sanitized("dir./file.txt"), "dir/file.txt");
+        assert_eq!(sanitized("CON./file.txt"), "_CON/file.txt");
+        assert_eq!(sanitized("..."), "_");
+    }
+
+    #[test]
+    fn invalid_characters_are_replaced() {
+        assert_eq!(sanitized("dir/ab:c*d?.txt"), "dir/ab_c_d_.txt");
+    }
+
+    #[test]
+    fn safe_names_are_unchanged() {
+        let name = sanitize_entry_name("dir/file.txt").unwrap();
+        assert_eq!(name.name, "dir/file.txt");
+        assert!(name.rewrites.is_empty());
+        assert!(sanitize_entry_name("").is_err());
+        assert!(sanitize_entry_name("a/..").is_err());
+    }
+}
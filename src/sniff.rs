@@ -0,0 +1,183 @@
+/// Number of leading bytes needed to classify any of the known file kinds.
+/// The tar signature sits at offset 257, which makes it the longest one.
+pub const SNIFF_LEN: usize = 512;
+
+/// A file type recognised by its leading bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileKind {
+    /// MIME type, e.g. "image/png".
+    pub mime: &'static str,
+    /// Extensions conventionally used for this type, canonical one first.
+    pub extensions: &'static [&'static str],
+}
+
+impl FileKind {
+    const fn new(mime: &'static str, extensions: &'static [&'static str]) -> Self {
+        FileKind { mime, extensions }
+    }
+
+    /// Returns true if `spec` names this kind. A spec is a MIME type ("image/png"),
+    /// a MIME wildcard ("image/*") or one of the kind's extensions ("png").
+    pub fn matches_spec(&self, spec: &str) -> bool {
+        let spec = spec.trim().trim_start_matches('.').to_lowercase();
+        if let Some(top_level) = spec.strip_suffix("/*") {
+            return self.mime.split('/').next() == Some(top_level);
+        }
+        self.mime == spec || self.extensions.contains(&spec.as_str())
+    }
+}
+
+pub const PNG: FileKind = FileKind::new("image/png", &["png"]);
+pub const JPEG: FileKind = FileKind::new("image/jpeg", &["jpg", "jpeg"]);
+pub const GIF: FileKind = FileKind::new("image/gif", &["gif"]);
+pub const WEBP: FileKind = FileKind::new("image/webp", &["webp"]);
+pub const BMP: FileKind = FileKind::new("image/bmp", &["bmp"]);
+pub const TIFF: FileKind = FileKind::new("image/tiff", &["tif", "tiff"]);
+pub const PDF: FileKind = FileKind::new("application/pdf", &["pdf"]);
+pub const ZIP: FileKind = FileKind::new("application/zip", &["zip"]);
+pub const GZIP: FileKind = FileKind::new("application/gzip", &["gz", "gzip"]);
+pub const BZIP2: FileKind = FileKind::new("application/x-bzip2", &["bz2"]);
+pub const XZ: FileKind = FileKind::new("application/x-xz", &["xz"]);
+pub const ZSTD: FileKind = FileKind::new("application/zstd", &["zst", "zstd"]);
+pub const SEVEN_ZIP: FileKind = FileKind::new("application/x-7z-compressed", &["7z"]);
+pub const RAR: FileKind = FileKind::new("application/vnd.rar", &["rar"]);
+pub const TAR: FileKind = FileKind::new("application/x-tar", &["tar"]);
+pub const ELF: FileKind = FileKind::new("application/x-elf", &["elf", "so", "o"]);
+pub const EXE: FileKind = FileKind::new("application/vnd.microsoft.portable-executable", &["exe", "dll"]);
+pub const SQLITE: FileKind = FileKind::new("application/vnd.sqlite3", &["sqlite", "db"]);
+pub const MP3: FileKind = FileKind::new("audio/mpeg", &["mp3"]);
+pub const WAV: FileKind = FileKind::new("audio/wav", &["wav"]);
+pub const FLAC: FileKind = FileKind::new("audio/flac", &["flac"]);
+pub const OGG: FileKind = FileKind::new("audio/ogg", &["ogg", "oga"]);
+pub const MP4: FileKind = FileKind::new("video/mp4", &["mp4", "m4a", "m4v"]);
+
+/// Every kind `detect` can return, used to validate `--type` values.
+pub const ALL_KINDS: &[FileKind] = &[
+    PNG, JPEG, GIF, WEBP, BMP, TIFF, PDF, ZIP, GZIP, BZIP2, XZ, ZSTD, SEVEN_ZIP, RAR, TAR, ELF, EXE,
+    SQLITE, MP3, WAV, FLAC, OGG, MP4,
+];
+
+/// Sizes of the DIB headers that follow a BMP file header, one per version of the format.
+const BMP_HEADER_SIZES: &[u32] = &[12, 16, 40, 52, 56, 64, 108, 124];
+
+/// Returns true if `head` starts with a plausible MPEG-1 Layer III frame header without CRC,
+/// as written by encoders that leave out an ID3 tag: the sync bits must be followed by a valid
+/// bitrate and sample rate.
+fn is_mp3_frame(head: &[u8]) -> bool {
+    match head {
+        [0xff, 0xfb, flags, ..] => {
+            let bitrate = flags >> 4;
+            let sample_rate = (flags >> 2) & 0b11;
+            bitrate != 0 && bitrate != 0b1111 && sample_rate != 0b11
+        }
+        _ => false,
+    }
+}
+
+/// Classifies a file by its magic number. `head` should hold the first `SNIFF_LEN`
+/// bytes of the file (or the whole file if it is shorter).
+pub fn detect(head: &[u8]) -> Option<FileKind> {
+    let at = |offset: usize, magic: &[u8]| head.get(offset..offset + magic.len()) == Some(magic);
+    let u32_at = |offset: usize| head.get(offset..offset + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+
+    let kind = if at(0, b"\x89PNG\r\n\x1a\n") {
+        PNG
+    } else if at(0, b"\xff\xd8\xff") {
+        JPEG
+    } else if at(0, b"GIF87a") || at(0, b"GIF89a") {
+        GIF
+    } else if at(0, b"RIFF") && at(8, b"WEBP") {
+        WEBP
+    } else if at(0, b"RIFF") && at(8, b"WAVE") {
+        WAV
+    } else if at(0, b"%PDF-") {
+        PDF
+    } else if at(0, b"PK\x03\x04") || at(0, b"PK\x05\x06") {
+        ZIP
+    } else if at(0, b"\x1f\x8b") {
+        GZIP
+    } else if at(0, b"BZh") {
+        BZIP2
+    } else if at(0, b"\xfd7zXZ\x00") {
+        XZ
+    } else if at(0, b"\x28\xb5\x2f\xfd") {
+        ZSTD
+    } else if at(0, b"7z\xbc\xaf\x27\x1c") {
+        SEVEN_ZIP
+    } else if at(0, b"Rar!\x1a\x07") {
+        RAR
+    } else if at(257, b"ustar") {
+        TAR
+    } else if at(0, b"\x7fELF") {
+        ELF
+    } else if at(0, b"MZ") && u32_at(0x3c).is_some_and(|pe| at(pe as usize, b"PE\0\0")) {
+        // "MZ" alone starts plenty of text; the DOS header points to the PE signature.
+        EXE
+    } else if at(0, b"SQLite format 3\x00") {
+        SQLITE
+    } else if at(0, b"ID3") || is_mp3_frame(head) {
+        MP3
+    } else if at(0, b"fLaC") {
+        FLAC
+    } else if at(0, b"OggS") {
+        OGG
+    } else if at(4, b"ftyp") {
+        MP4
+    } else if at(0, b"II*\x00") || at(0, b"MM\x00*") {
+        TIFF
+    } else if at(0, b"BM") && at(6, &[0; 4]) && u32_at(14).is_some_and(|size| BMP_HEADER_SIZES.contains(&size)) {
+        // "BM" alone starts plenty of text; the reserved bytes must be zero and the DIB
+        // header one of the known sizes.
+        BMP
+    } else {
+        return None;
+    };
+    Some(kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_starting_like_weak_signatures_is_not_classified() {
+        assert_eq!(detect(b"BMW report for the third quarter"), None);
+        assert_eq!(detect(b"MZ is the start of a DOS executable header."), None);
+        assert_eq!(detect(b"\xff\xfb\xff\xff"), None);
+    }
+
+    #[test]
+    fn executables_are_found_through_the_pe_header_offset() {
+        let mut exe = vec![0; 0x100];
+        exe[..2].copy_from_slice(b"MZ");
+        exe[0x3c..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+        exe[0x80..0x84].copy_from_slice(b"PE\0\0");
+        assert_eq!(detect(&exe), Some(EXE));
+
+        exe[0x3c..0x40].copy_from_slice(&0x1000u32.to_le_bytes());
+        assert_eq!(detect(&exe), None);
+    }
+
+    #[test]
+    fn bitmaps_need_zero_reserved_bytes_and_a_known_header_size() {
+        let mut bmp = vec![0; 54];
+        bmp[..2].copy_from_slice(b"BM");
+        bmp[2..6].copy_from_slice(&54u32.to_le_bytes());
+        bmp[10..14].copy_from_slice(&54u32.to_le_bytes());
+        bmp[14..18].copy_from_slice(&40u32.to_le_bytes());
+        assert_eq!(detect(&bmp), Some(BMP));
+
+        bmp[14..18].copy_from_slice(&41u32.to_le_bytes());
+        assert_eq!(detect(&bmp), None);
+        bmp[14..18].copy_from_slice(&40u32.to_le_bytes());
+        bmp[7] = 1;
+        assert_eq!(detect(&bmp), None);
+    }
+
+    #[test]
+    fn mp3_frames_need_a_valid_bitrate_and_sample_rate() {
+        assert_eq!(detect(b"\xff\xfb\x90\x64"), Some(MP3));
+        assert_eq!(detect(b"ID3\x04\x00"), Some(MP3));
+        assert_eq!(detect(b"\xff\xfb\x0c\x00"), None);
+    }
+}
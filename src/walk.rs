@@ -0,0 +1,98 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::archive;
+
+/// Controls how an input directory is scanned for archives.
+#[derive(Debug, Clone, Default)]
+pub struct WalkOptions {
+    /// Descend into subdirectories instead of only scanning the top level.
+    pub recursive: bool,
+    /// Maximum number of directory levels to descend below the input directory.
+    /// `None` means unlimited. Ignored unless `recursive` is set.
+    pub max_depth: Option<usize>,
+    /// Descend into symlinked directories. Symlinked files are always considered.
+    pub follow_symlinks: bool,
+    /// Descend into directories whose names start with a dot.
+    pub include_hidden: bool,
+}
+
+/// Returns every archive or compressed file under `dir` (recognised by its file name suffix),
+/// sorted by path so runs are reproducible.
+/// Subdirectories that cannot be read are passed to `warn` and skipped rather than aborting the scan.
+pub fn find_archives(dir: &Path, options: &WalkOptions, warn: &mut dyn FnMut(String)) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut found = Vec::new();
+    // Canonical paths of visited directories, used to break symlink loops.
+    let mut visited = HashSet::new();
+    if let Ok(canonical) = fs::canonicalize(dir) {
+        visited.insert(canonical);
+    }
+
+    // The top-level directory must be readable; errors below it are only warnings.
+    let mut pending = vec![(dir.to_path_buf(), 0usize)];
+    let mut is_top_level = true;
+    while let Some((current, depth)) = pending.pop() {
+        let entries = match fs::read_dir(&current) {
+            Ok(entries) => entries,
+            Err(e) if !is_top_level => {
+                warn(format!("Warning: Cannot read directory {}: {}", current.display(), e));
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        is_top_level = false;
+
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn(format!("Warning: Cannot read an entry of {}: {}", current.display(), e));
+                    continue;
+                }
+            };
+            let path = entry.path();
+
+            if path.is_file() {
+                if archive::is_input_path(&path) {
+                    found.push(path);
+                }
+                continue;
+            }
+
+            if !options.recursive || !path.is_dir() {
+                continue;
+            }
+            if options.max_depth.is_some_and(|max| depth >= max) {
+                continue;
+            }
+            if !options.include_hidden && is_hidden(&path) {
+                continue;
+            }
+            let is_symlink = entry.file_type().map(|t| t.is_symlink()).unwrap_or(false);
+            if is_symlink && !options.follow_symlinks {
+                continue;
+            }
+            // Only descend into each real directory once.
+            if let Ok(canonical) = fs::canonicalize(&path) {
+                if !visited.insert(canonical) {
+                    continue;
+                }
+            }
+            pending.push((path, depth + 1));
+        }
+    }
+
+    found.sort();
+    Ok(found)
+}
+
+/// Returns true if the last path component starts with a dot.
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(OsStr::to_str)
+        .map(|name| name.starts_with('.'))
+        .unwrap_or(false)
+}